@@ -0,0 +1,111 @@
+/**
+ * Command module for requests from the Rust app
+ *
+ * Commands arrive on stdin as JSON (one per line). Every command carries a
+ * request ID that is echoed back in an 'ack' or 'command_error' message.
+ */
+
+import * as output from './output.js';
+import { RoonClient } from './roon/client.js';
+import { TransportControl } from './roon/transport.js';
+
+export type CommandName = TransportControl | 'seek' | 'fetch_artwork';
+
+export interface SidecarCommand {
+  id: number;
+  command: CommandName;
+  zone_id?: string; // transport commands
+  seconds?: number; // seek only
+  image_key?: string; // fetch_artwork only
+}
+
+const TRANSPORT_CONTROLS: TransportControl[] = ['play', 'pause', 'playpause', 'stop', 'previous', 'next'];
+
+/**
+ * Parse a single stdin line into a command
+ * Throws if the line is not a well-formed command
+ */
+function parseCommand(line: string): SidecarCommand {
+  const data = JSON.parse(line);
+
+  if (typeof data !== 'object' || data === null) {
+    throw new Error('Command must be a JSON object');
+  }
+  if (typeof data.id !== 'number') {
+    throw new Error('Command is missing a numeric id');
+  }
+
+  return data as SidecarCommand;
+}
+
+/**
+ * Get the zone a transport command targets
+ */
+function requireZoneId(command: SidecarCommand): string {
+  if (typeof command.zone_id !== 'string' || command.zone_id.length === 0) {
+    throw new Error(`${command.command} command requires a zone_id`);
+  }
+  return command.zone_id;
+}
+
+/**
+ * Execute a command against the Roon client
+ */
+async function executeCommand(command: SidecarCommand, client: RoonClient): Promise<void> {
+  if (command.command === 'fetch_artwork') {
+    if (typeof command.image_key !== 'string' || command.image_key.length === 0) {
+      throw new Error('fetch_artwork command requires an image_key');
+    }
+    const sent = await client.getImageManager().sendArtwork(command.image_key);
+    if (!sent) {
+      throw new Error(`Artwork not available for ${command.image_key}`);
+    }
+    return;
+  }
+
+  const zoneId = requireZoneId(command);
+  const transport = client.getTransportManager();
+
+  if (command.command === 'seek') {
+    if (typeof command.seconds !== 'number' || command.seconds < 0) {
+      throw new Error('Seek command requires a non-negative seconds value');
+    }
+    await transport.seek(zoneId, command.seconds);
+    return;
+  }
+
+  if (!TRANSPORT_CONTROLS.includes(command.command)) {
+    throw new Error(`Unknown command: ${command.command}`);
+  }
+
+  await transport.control(zoneId, command.command);
+}
+
+/**
+ * Handle one line read from stdin and reply with an ack or command_error
+ */
+export async function handleCommandLine(line: string, client: RoonClient): Promise<void> {
+  if (line.trim().length === 0) {
+    return;
+  }
+
+  let command: SidecarCommand;
+  try {
+    command = parseCommand(line);
+  } catch (error) {
+    // Without a request ID there is nothing to reply to
+    output.warn(`Ignoring malformed command: ${line}`, error);
+    return;
+  }
+
+  output.info(`Received command #${command.id}: ${command.command}`);
+
+  try {
+    await executeCommand(command, client);
+    output.emitAck(command.id);
+  } catch (error) {
+    const message = error instanceof Error ? error.message : String(error);
+    output.warn(`Command #${command.id} failed: ${message}`);
+    output.emitCommandError(command.id, message);
+  }
+}
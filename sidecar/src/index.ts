@@ -7,11 +7,14 @@
  * and emits JSON messages to stdout for consumption by the main Rust application.
  *
  * Communication protocol:
+ * - stdin: JSON commands from the Rust app (one per line)
  * - stdout: JSON messages (one per line)
  * - stderr: Debug/log messages
  */
 
+import * as readline from 'readline';
 import { RoonClient } from './roon/client.js';
+import { handleCommandLine } from './commands.js';
 import * as output from './output.js';
 
 /**
@@ -22,6 +25,9 @@ function main(): void {
   output.info(`Node version: ${process.version}`);
   output.info(`Platform: ${process.platform} ${process.arch}`);
 
+  // Announce protocol version and capabilities before anything else
+  output.emitHello();
+
   // Create and start Roon client
   const client = new RoonClient();
 
@@ -57,8 +63,14 @@ function main(): void {
     output.info('Parent process closed stdin, shutting down...');
     shutdown();
   });
-  // Resume stdin so the 'end' event fires when parent dies
-  process.stdin.resume();
+  // Read commands from stdin - this also keeps stdin flowing so the
+  // 'end' event fires when parent dies
+  const commands = readline.createInterface({ input: process.stdin, terminal: false });
+  commands.on('line', (line) => {
+    handleCommandLine(line, client).catch((error) => {
+      output.error('Error handling command:', error);
+    });
+  });
 
   // Handle uncaught errors
   process.on('uncaughtException', (error) => {
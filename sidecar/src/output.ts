@@ -5,10 +5,27 @@
  * Each message must be a single line of JSON.
  */
 
+/**
+ * IPC protocol version - must match PROTOCOL_VERSION in the Rust app
+ * Only bump for breaking changes; additive features are advertised as capabilities
+ */
+export const PROTOCOL_VERSION = 2;
+
+/**
+ * Optional features this sidecar supports
+ */
+export const CAPABILITIES = ['transport_control', 'seek', 'fetch_artwork'];
+
 export type PlaybackState = 'playing' | 'paused' | 'stopped' | 'loading';
 
 export type ConnectionState = 'discovering' | 'not_authorized' | 'connected' | 'disconnected';
 
+export interface HelloOutput {
+  type: 'hello';
+  protocol_version: number;
+  capabilities: string[];
+}
+
 export interface NowPlayingOutput {
   type: 'now_playing';
   zone_id: string; // NEW: Zone identifier
@@ -16,7 +33,15 @@ export interface NowPlayingOutput {
   artist: string;
   album: string;
   state: PlaybackState;
-  artwork?: string; // base64 data URL
+  image_key?: string; // artwork is sent separately in an artwork message
+  seek_position?: number; // seconds
+  length?: number; // seconds
+}
+
+export interface SeekChangedOutput {
+  type: 'seek_changed';
+  zone_id: string;
+  seek_position: number; // seconds
 }
 
 export interface ZoneInfo {
@@ -27,10 +52,18 @@ export interface ZoneInfo {
     title: string;
     artist: string;
     album: string;
-    artwork?: string;
+    image_key?: string;
+    seek_position?: number;
+    length?: number;
   };
 }
 
+export interface ArtworkOutput {
+  type: 'artwork';
+  image_key: string;
+  data: string; // base64 data URL
+}
+
 export interface ZoneListOutput {
   type: 'zone_list';
   zones: ZoneInfo[];
@@ -47,7 +80,27 @@ export interface ErrorOutput {
   message: string;
 }
 
-export type SidecarOutput = NowPlayingOutput | ZoneListOutput | StatusOutput | ErrorOutput;
+export interface AckOutput {
+  type: 'ack';
+  id: number;
+}
+
+export interface CommandErrorOutput {
+  type: 'command_error';
+  id: number;
+  message: string;
+}
+
+export type SidecarOutput =
+  | HelloOutput
+  | NowPlayingOutput
+  | SeekChangedOutput
+  | ArtworkOutput
+  | ZoneListOutput
+  | StatusOutput
+  | ErrorOutput
+  | AckOutput
+  | CommandErrorOutput;
 
 /**
  * Emit a JSON message to stdout
@@ -66,6 +119,17 @@ export function emit(data: SidecarOutput): void {
   }
 }
 
+/**
+ * Emit the protocol handshake - must be the first message on stdout
+ */
+export function emitHello(): void {
+  emit({
+    type: 'hello',
+    protocol_version: PROTOCOL_VERSION,
+    capabilities: CAPABILITIES,
+  });
+}
+
 /**
  * Emit a now playing update
  */
@@ -75,7 +139,9 @@ export function emitNowPlaying(
   artist: string,
   album: string,
   state: PlaybackState,
-  artwork?: string
+  image_key?: string,
+  seek_position?: number,
+  length?: number
 ): void {
   emit({
     type: 'now_playing',
@@ -84,7 +150,32 @@ export function emitNowPlaying(
     artist,
     album,
     state,
-    artwork,
+    image_key,
+    seek_position,
+    length,
+  });
+}
+
+/**
+ * Emit a playback position change for a zone
+ */
+export function emitSeekChanged(zone_id: string, seek_position: number): void {
+  emit({
+    type: 'seek_changed',
+    zone_id,
+    seek_position,
+  });
+}
+
+/**
+ * Emit album artwork for an image key
+ * Sent once per key; now_playing and zone_list messages refer to it by key
+ */
+export function emitArtwork(image_key: string, data: string): void {
+  emit({
+    type: 'artwork',
+    image_key,
+    data,
   });
 }
 
@@ -119,6 +210,27 @@ export function emitError(message: string): void {
   });
 }
 
+/**
+ * Acknowledge a command from the Rust app
+ */
+export function emitAck(id: number): void {
+  emit({
+    type: 'ack',
+    id,
+  });
+}
+
+/**
+ * Report that a command from the Rust app failed
+ */
+export function emitCommandError(id: number, message: string): void {
+  emit({
+    type: 'command_error',
+    id,
+    message,
+  });
+}
+
 /**
  * Log a debug message to stderr (won't interfere with stdout JSON)
  */
@@ -396,6 +396,21 @@ export class RoonClient {
       output.error('Error stopping Roon client:', error);
     }
   }
+
+  /**
+   * Get the transport manager (used to execute commands from the Rust app)
+   */
+  getTransportManager(): TransportManager {
+    return this.transportManager;
+  }
+
+  /**
+   * Get the image manager (used to re-send artwork on request)
+   */
+  getImageManager(): ImageManager {
+    return this.imageManager;
+  }
+
 /**
  * Get authorization status
  */
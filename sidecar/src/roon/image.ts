@@ -103,6 +103,8 @@ const IMAGE_FETCH_TIMEOUT_MS = 10000;
 export class ImageManager {
   private imageService: ImageService | null = null;
   private cache = new LRUCache<string, string>(MAX_CACHE_SIZE, CACHE_TTL_MS);
+  // Image keys already sent to Rust in this session
+  private sentKeys = new Set<string>();
 
   /**
    * Set the Roon image service (called after core connects)
@@ -119,9 +121,36 @@ export class ImageManager {
   clearImageService(): void {
     this.imageService = null;
     this.clearCache();
+    this.sentKeys.clear();
     output.debug('Image service cleared');
   }
 
+  /**
+   * Send artwork to Rust unless it was already sent in this session
+   * Resolves once the artwork message is out (or fetching failed)
+   */
+  async ensureArtworkSent(imageKey: string): Promise<void> {
+    if (this.sentKeys.has(imageKey)) {
+      return;
+    }
+    await this.sendArtwork(imageKey);
+  }
+
+  /**
+   * Fetch artwork and emit it as an artwork message
+   * Returns false if the artwork could not be fetched
+   */
+  async sendArtwork(imageKey: string): Promise<boolean> {
+    const dataUrl = await this.fetchArtwork(imageKey);
+    if (!dataUrl) {
+      return false;
+    }
+
+    output.emitArtwork(imageKey, dataUrl);
+    this.sentKeys.add(imageKey);
+    return true;
+  }
+
   /**
    * Fetch artwork for a given image key and convert to base64 data URL
    * Returns cached version if available
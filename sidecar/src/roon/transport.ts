@@ -10,8 +10,15 @@ import { ImageManager } from './image.js';
 export interface TransportService {
   subscribe_zones: (callback: (response: string, data: any) => void) => void;
   subscribe_outputs: (callback: (response: string, data: any) => void) => void;
+  control: (zone_or_output_id: string, control: string, callback?: (error: any) => void) => void;
+  seek: (zone_or_output_id: string, how: string, seconds: number, callback?: (error: any) => void) => void;
 }
 
+/**
+ * Transport controls understood by Roon's control() call
+ */
+export type TransportControl = 'play' | 'pause' | 'playpause' | 'stop' | 'previous' | 'next';
+
 interface Zone {
   zone_id: string;
   display_name: string;
@@ -51,13 +58,31 @@ interface NowPlayingData {
   };
 }
 
+interface SeekChange {
+  zone_id: string;
+  seek_position?: number;
+  queue_time_remaining?: number;
+}
+
 interface ZonesData {
   zones?: Zone[];
   zones_changed?: Zone[];
   zones_removed?: string[];
-  zones_seek_changed?: any[];
+  zones_seek_changed?: SeekChange[];
 }
 
+/**
+ * Last position reported to Rust for a zone
+ */
+interface ReportedPosition {
+  position: number; // seconds
+  at: number; // Date.now() when reported
+}
+
+// Rust interpolates the position locally, so Roon's once-a-second seek updates
+// are only forwarded when they drift from that estimate by more than this
+const SEEK_DRIFT_THRESHOLD_S = 2;
+
 interface OutputsData {
   outputs?: Output[];
   outputs_changed?: Output[];
@@ -72,6 +97,7 @@ export class TransportManager {
   private imageManager: ImageManager;
   private allZones: Map<string, Zone> = new Map(); // Track ALL zones by zone_id
   private allOutputs: Map<string, Output> = new Map(); // Track ALL outputs by output_id
+  private reportedPositions: Map<string, ReportedPosition> = new Map(); // Last position sent per zone
 
   constructor(imageManager: ImageManager) {
     this.imageManager = imageManager;
@@ -99,9 +125,52 @@ export class TransportManager {
     this.transportService = null;
     this.allZones.clear();
     this.allOutputs.clear();
+    this.reportedPositions.clear();
     output.debug('Transport service cleared');
   }
 
+  /**
+   * Send a transport control to a zone
+   * Resolves once Roon has accepted the request
+   */
+  control(zoneId: string, control: TransportControl): Promise<void> {
+    return new Promise((resolve, reject) => {
+      if (!this.transportService) {
+        reject(new Error('Transport service not available'));
+        return;
+      }
+
+      this.transportService.control(zoneId, control, (error) => {
+        // Roon passes false on success and an error string on failure
+        if (error) {
+          reject(new Error(String(error)));
+        } else {
+          resolve();
+        }
+      });
+    });
+  }
+
+  /**
+   * Seek to an absolute position (in seconds) in the zone's current track
+   */
+  seek(zoneId: string, seconds: number): Promise<void> {
+    return new Promise((resolve, reject) => {
+      if (!this.transportService) {
+        reject(new Error('Transport service not available'));
+        return;
+      }
+
+      this.transportService.seek(zoneId, 'absolute', seconds, (error) => {
+        if (error) {
+          reject(new Error(String(error)));
+        } else {
+          resolve();
+        }
+      });
+    });
+  }
+
   /**
    * Emit the current zone list to Rust
    * Includes both active zones and standby outputs (which aren't in zones yet)
@@ -133,8 +202,10 @@ export class TransportManager {
           title,
           artist,
           album,
-          // Note: We don't include artwork in zone_list to keep it lightweight
-          // Artwork is only included in now_playing messages
+          // Only the key - the image itself is sent once as an artwork message
+          image_key: zone.now_playing.image_key,
+          seek_position: zone.now_playing.seek_position,
+          length: zone.now_playing.length,
         };
       }
 
@@ -304,9 +375,11 @@ export class TransportManager {
       output.info('=== HANDLING ZONES UPDATE ===');
 
       // Handle seek position changes separately - these don't contain full zone data
-      if (data.zones_seek_changed && data.zones_seek_changed.length > 0 && !data.zones && !data.zones_changed) {
-        output.debug(`Seek position update for ${data.zones_seek_changed.length} zone(s) - ignoring (no zone data)`);
-        return;
+      if (data.zones_seek_changed && data.zones_seek_changed.length > 0) {
+        this.handleSeekChanges(data.zones_seek_changed);
+        if (!data.zones && !data.zones_changed) {
+          return;
+        }
       }
 
       // Handle zones_removed
@@ -354,6 +427,37 @@ export class TransportManager {
     }
   }
 
+  /**
+   * Handle seek position updates from Roon
+   * Only forwards positions that differ from what Rust is interpolating (e.g. user seeks)
+   */
+  private handleSeekChanges(changes: SeekChange[]): void {
+    const now = Date.now();
+
+    changes.forEach(change => {
+      if (typeof change.seek_position !== 'number') {
+        return;
+      }
+
+      const zone = this.allZones.get(change.zone_id);
+      if (zone?.now_playing) {
+        zone.now_playing.seek_position = change.seek_position;
+      }
+
+      const reported = this.reportedPositions.get(change.zone_id);
+      const isPlaying = zone?.state === 'playing';
+      const expected = reported
+        ? reported.position + (isPlaying ? (now - reported.at) / 1000 : 0)
+        : undefined;
+
+      if (expected === undefined || Math.abs(expected - change.seek_position) > SEEK_DRIFT_THRESHOLD_S) {
+        output.emitSeekChanged(change.zone_id, change.seek_position);
+        this.reportedPositions.set(change.zone_id, { position: change.seek_position, at: now });
+        output.debug(`Emitted seek change for zone ${change.zone_id}: ${change.seek_position}s`);
+      }
+    });
+  }
+
   /**
    * Extract metadata from now_playing data
    */
@@ -398,16 +502,27 @@ export class TransportManager {
     // Map Roon state to our state enum
     const playbackState = this.mapRoonStateToPlaybackState(state);
 
-    // Fetch artwork if available (image manager has caching)
-    let artwork: string | undefined;
+    // Make sure Rust has the artwork before it sees the key (image manager has caching)
     const imageKey = nowPlaying.image_key;
 
     if (imageKey) {
-      artwork = await this.imageManager.fetchArtwork(imageKey);
+      await this.imageManager.ensureArtworkSent(imageKey);
     }
 
     // Emit the now playing data with zone_id
-    output.emitNowPlaying(zone.zone_id, title, artist, album, playbackState, artwork);
+    output.emitNowPlaying(
+      zone.zone_id,
+      title,
+      artist,
+      album,
+      playbackState,
+      imageKey,
+      nowPlaying.seek_position,
+      nowPlaying.length
+    );
+    if (typeof nowPlaying.seek_position === 'number') {
+      this.reportedPositions.set(zone.zone_id, { position: nowPlaying.seek_position, at: Date.now() });
+    }
     output.debug(`Emitted now playing for zone ${zone.zone_id}: ${title} by ${artist} (${playbackState})`);
   }
 }
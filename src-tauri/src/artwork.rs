@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Context, Result};
+use parking_lot::Mutex;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::lru::Lru;
+use crate::paths;
+
+/// Number of artwork images kept in memory (each is a small encoded JPEG/PNG)
+const MAX_MEMORY_ENTRIES: usize = 32;
+
+/// Number of artwork files kept on disk before the oldest are pruned
+const MAX_DISK_ENTRIES: usize = 500;
+
+/// Maximum size of a single encoded artwork image
+/// The sidecar sends images base64-encoded in one JSON line, so its line
+/// limit is derived from this one with `max_artwork_message_size`
+pub const MAX_ARTWORK_BYTES: usize = 512 * 1024;
+
+/// Room in an artwork message for everything but the base64 data:
+/// the JSON keys, the image_key and the data URL prefix
+const ARTWORK_ENVELOPE_BYTES: usize = 4 * 1024;
+
+/// Longest message line that can carry an image of `MAX_ARTWORK_BYTES`
+/// Base64 turns every 3 bytes (rounded up) into 4 characters
+pub const fn max_artwork_message_size() -> usize {
+    MAX_ARTWORK_BYTES.div_ceil(3) * 4 + ARTWORK_ENVELOPE_BYTES
+}
+
+/// Encoded artwork bytes, shared without copying
+pub type ArtworkBytes = Arc<Vec<u8>>;
+
+/// Album artwork cache keyed by Roon's image_key
+///
+/// The sidecar sends each image once as an `artwork` message; everything else
+/// refers to it by key. Images live in memory (bounded LRU) and on disk so they
+/// survive sidecar and app restarts.
+pub struct ArtworkCache {
+    memory: Mutex<Lru<String, ArtworkBytes>>,
+    dir: Option<PathBuf>,
+}
+
+impl ArtworkCache {
+    /// Create a cache backed by the standard artwork directory
+    /// Falls back to memory-only if the directory can't be created
+    pub fn new() -> Self {
+        let dir = paths::artwork_dir().and_then(|dir| match paths::create_private_dir(&dir) {
+            Ok(()) => Some(dir),
+            Err(e) => {
+                log::warn!("Artwork cache directory unavailable, using memory only: {}", e);
+                None
+            }
+        });
+
+        if let Some(dir) = &dir {
+            Self::prune_disk(dir);
+        }
+
+        Self {
+            memory: Mutex::new(Lru::new(MAX_MEMORY_ENTRIES)),
+            dir,
+        }
+    }
+
+    /// Store artwork received from the sidecar as a base64 data URL
+    pub fn insert_data_url(&self, image_key: &str, data_url: &str) -> Result<ArtworkBytes> {
+        let bytes = Arc::new(decode_data_url(data_url)?);
+
+        if let Some(path) = self.disk_path(image_key) {
+            if let Err(e) = paths::write_atomic(&path, &bytes) {
+                log::warn!("Failed to write artwork {} to disk: {}", image_key, e);
+            }
+        }
+
+        self.memory.lock().insert(image_key.to_string(), bytes.clone());
+        Ok(bytes)
+    }
+
+    /// Look up artwork by image_key, falling back to disk on a memory miss
+    pub fn get(&self, image_key: &str) -> Option<ArtworkBytes> {
+        if let Some(bytes) = self.memory.lock().get(image_key).cloned() {
+            return Some(bytes);
+        }
+
+        let path = self.disk_path(image_key)?;
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) if !bytes.is_empty() && bytes.len() <= MAX_ARTWORK_BYTES => Arc::new(bytes),
+            Ok(_) => {
+                log::warn!("Discarding invalid cached artwork {}", image_key);
+                let _ = std::fs::remove_file(&path);
+                return None;
+            }
+            Err(_) => return None,
+        };
+
+        self.memory.lock().insert(image_key.to_string(), bytes.clone());
+        Some(bytes)
+    }
+
+    /// File path for an image_key
+    /// Keys that aren't plain identifiers are hashed so they can't escape the directory
+    fn disk_path(&self, image_key: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let is_safe = !image_key.is_empty()
+            && image_key.len() <= 128
+            && image_key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        let file_name = if is_safe {
+            image_key.to_string()
+        } else {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            image_key.hash(&mut hasher);
+            format!("h{:016x}", hasher.finish())
+        };
+
+        Some(dir.join(file_name))
+    }
+
+    /// Remove the oldest files when the disk cache grows past its limit
+    fn prune_disk(dir: &Path) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        let mut files: Vec<(std::time::SystemTime, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect();
+
+        if files.len() <= MAX_DISK_ENTRIES {
+            return;
+        }
+
+        files.sort();
+        let excess = files.len() - MAX_DISK_ENTRIES;
+        for (_, path) in files.into_iter().take(excess) {
+            let _ = std::fs::remove_file(path);
+        }
+        log::info!("Pruned {} old artwork files from cache", excess);
+    }
+}
+
+/// Decode a base64 data URL (or bare base64) into image bytes
+fn decode_data_url(data_url: &str) -> Result<Vec<u8>> {
+    // Strip data URL prefix if present
+    let base64_data = if data_url.starts_with("data:") {
+        data_url
+            .split(',')
+            .nth(1)
+            .context("Invalid data URL format")?
+    } else {
+        data_url
+    };
+
+    // Check for empty base64 data
+    if base64_data.trim().is_empty() {
+        return Err(anyhow!("Empty base64 data in artwork"));
+    }
+
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64_data)
+        .context("Failed to decode base64 artwork")?;
+
+    if bytes.len() > MAX_ARTWORK_BYTES {
+        return Err(anyhow!(
+            "Artwork is {} bytes, exceeds maximum of {}",
+            bytes.len(), MAX_ARTWORK_BYTES
+        ));
+    }
+
+    Ok(bytes)
+}
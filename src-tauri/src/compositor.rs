@@ -1,109 +1,347 @@
 use anyhow::{anyhow, Context, Result};
 use image::{Rgba, RgbaImage};
-use imageproc::drawing::draw_text_mut;
-use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use ab_glyph::PxScale;
+use parking_lot::{Mutex, RwLock};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::artwork::ArtworkCache;
+use crate::fonts::FontChain;
+use crate::lru::Lru;
+use crate::quantize::{self, Swatch};
+use crate::shaping::{self, ShapedText};
+use crate::template::{Template, TemplateFields};
+use crate::theme::{self, Colors};
+use crate::truncate::{self, Truncation};
+use crate::types::{
+    AppState, ArtworkColor, DisplaySettings, IconLayout, PausedStyle, PlaybackState, ProgressBarStyle, TextOverflow,
+};
 
 /// Maximum dimensions for decoded images (prevent OOM attacks)
 const MAX_IMAGE_DIMENSION: u32 = 4096;
 
-/// Get appropriate text color based on system appearance
+/// Icons are drawn at 3x resolution for sharp Retina text
+const SCALE_FACTOR: u32 = 3;
+/// Menu bar height is 22pt
+const MENU_BAR_HEIGHT_PT: u32 = 22;
+/// The square album art, also the size of the loading spinner
+const ALBUM_ART_SIZE: u32 = MENU_BAR_HEIGHT_PT * SCALE_FACTOR;
+
+/// Time between marquee and loading spinner frames
+pub const ANIMATION_FRAME: Duration = Duration::from_millis(80);
+/// Distance the marquee moves per frame: 1pt
+const MARQUEE_STEP_PX: u32 = SCALE_FACTOR;
+/// Frames the marquee rests at each end of the text
+const MARQUEE_PAUSE_FRAMES: u64 = 20;
+/// Encoded marquee frames kept for the current text
+const MAX_MARQUEE_FRAMES: usize = 256;
+/// Decoded, resized artwork kept for recent tracks
+const MAX_DECODED_ARTWORK: usize = 16;
+/// Finished icons kept, so redraws with unchanged inputs skip compositing
+const MAX_CACHED_ICONS: usize = 32;
+/// Colours kept from each artwork's palette
+const ARTWORK_SWATCHES: usize = 8;
+
+/// Spokes in the loading spinner; one step per frame is about a turn a second
+const SPINNER_SPOKES: u64 = 12;
+
+/// Opacity of everything but the pause symbol while paused
+const PAUSED_OPACITY: f32 = 0.45;
+/// Pause symbol size at 3x: two 8px bars 8px apart, 10pt tall
+const PAUSE_BAR_WIDTH: u32 = 8;
+const PAUSE_SYMBOL_WIDTH: u32 = 3 * PAUSE_BAR_WIDTH;
+const PAUSE_SYMBOL_HEIGHT: u32 = 10 * SCALE_FACTOR;
+
+/// Whether the menu bar is dark, which picks the theme's palette
 /// Uses the dark-light crate which properly caches and uses native APIs
-fn get_text_color() -> Rgba<u8> {
-    match dark_light::detect() {
-        dark_light::Mode::Dark => Rgba([255, 255, 255, 255]), // White text for dark mode
-        dark_light::Mode::Light | dark_light::Mode::Default => Rgba([0, 0, 0, 255]), // Black text for light mode
+fn is_dark_mode() -> bool {
+    matches!(dark_light::detect(), dark_light::Mode::Dark)
+}
+
+/// Hash of anything hashable, for cache keys
+fn hash_of(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Artwork as a monochrome template image, whose colour the OS replaces
+/// Alpha follows brightness so the tinted image still reads like the
+/// artwork: bright parts are opaque under a white (dark mode) tint and dark
+/// parts under a black one
+fn template_mask(image: &RgbaImage, dark: bool) -> RgbaImage {
+    let mut mask = image.clone();
+    for pixel in mask.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let brightness = (0.2126 * f32::from(r) + 0.7152 * f32::from(g) + 0.0722 * f32::from(b)) / 255.0;
+        let coverage = if dark { brightness } else { 1.0 - brightness };
+        *pixel = Rgba([0, 0, 0, (coverage * f32::from(a)).round() as u8]);
     }
+    mask
 }
 
-pub struct Compositor {
-    font: FontVec,
+/// Artwork decoded at icon size, with the colours it's made of
+struct DecodedArtwork {
+    image: RgbaImage,
+    /// Most prominent first
+    swatches: Vec<Swatch>,
 }
 
-impl Compositor {
-    pub fn new() -> Result<Self> {
-        // Load SF Pro Text (macOS native system font) from system font directory
-        // SF Pro is the standard system font for macOS since macOS 10.11
-        let font_path = "/System/Library/Fonts/SFNS.ttf";
+/// Track progress to draw as a thin bar along the bottom of the icon
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressBar {
+    /// Fraction played, 0.0 - 1.0
+    pub fraction: f32,
+    pub style: ProgressBarStyle,
+}
+
+/// Scrolling state for text that doesn't fit
+/// The text is rendered once into a strip that each frame crops from,
+/// and encoded frames are kept so repeated cycles cost almost nothing
+struct Marquee {
+    /// Identifies the look the frames were made for: text, layout, theme,
+    /// artwork and progress bar style, but not the progress itself
+    key: u64,
+    started: Instant,
+    strip: RgbaImage,
+    frames: HashMap<(u32, Option<u32>), Vec<u8>>,
+}
 
-        let font_data = std::fs::read(font_path)
-            .context("Failed to load SF Pro system font. Ensure running on macOS.")?;
+impl Marquee {
+    /// Horizontal offset into the strip for the current time:
+    /// rest at the start, scroll to the end, rest, then jump back
+    fn offset(&self, visible_width: u32) -> u32 {
+        let travel = self.strip.width().saturating_sub(visible_width);
+        let scroll_frames = u64::from(travel.div_ceil(MARQUEE_STEP_PX));
+        let cycle = scroll_frames + 2 * MARQUEE_PAUSE_FRAMES;
+        let frame = (self.started.elapsed().as_millis() / ANIMATION_FRAME.as_millis()) as u64 % cycle;
+        let scrolled = frame.saturating_sub(MARQUEE_PAUSE_FRAMES).min(scroll_frames);
+        (scrolled as u32 * MARQUEE_STEP_PX).min(travel)
+    }
+}
 
-        // Parse font once at construction time, cache for reuse
-        let font = FontVec::try_from_vec(font_data)
-            .map_err(|_| anyhow!("Failed to parse SF Pro font data"))?;
+pub struct Compositor {
+    /// Rebuilt when the `display.fonts` setting changes
+    fonts: RwLock<Arc<FontChain>>,
+    marquee: Mutex<Option<Marquee>>,
+    /// Resized artwork and its palette by (artwork hash, size)
+    artwork: Mutex<Lru<(u64, u32), Arc<DecodedArtwork>>>,
+    /// Encoded icons by a hash of everything that went into them
+    icons: Mutex<Lru<u64, Vec<u8>>>,
+    /// Whether the last icon was a scrolling marquee or spinner frame
+    animating: AtomicBool,
+    /// Reference time for the loading spinner's rotation
+    started: Instant,
+}
 
-        Ok(Self { font })
+impl Compositor {
+    /// Create a compositor with the default font chain
+    /// SF Pro on macOS, the fontconfig sans-serif font elsewhere, with the
+    /// embedded fallback font when neither is available
+    pub fn new() -> Self {
+        Self {
+            fonts: RwLock::new(Arc::new(FontChain::new(&[]))),
+            marquee: Mutex::new(None),
+            artwork: Mutex::new(Lru::new(MAX_DECODED_ARTWORK)),
+            icons: Mutex::new(Lru::new(MAX_CACHED_ICONS)),
+            animating: AtomicBool::new(false),
+            started: Instant::now(),
+        }
     }
 
-    /// Extract the primary (first) artist from a potentially multi-artist string
-    /// Roon sends multiple artists separated by " / ", but we only want to show the first
-    fn get_primary_artist(artist: &str) -> &str {
-        let first = artist.split(" / ").next().unwrap_or(artist);
-        // Handle edge case where artist is " / Something" or just " / "
-        let trimmed = first.trim();
-        if trimmed.is_empty() {
-            // Try to get the second part if first was empty
-            artist.split(" / ").nth(1).map(str::trim).filter(|s| !s.is_empty()).unwrap_or(artist.trim())
-        } else {
-            trimmed
+    /// The font chain for the configured font list, rebuilding it if the list changed
+    fn font_chain(&self, configured: &[String]) -> Arc<FontChain> {
+        let current = self.fonts.read().clone();
+        if current.configured() == configured {
+            return current;
         }
+
+        log::info!("Font list changed to {:?}", configured);
+        let chain = Arc::new(FontChain::new(configured));
+        *self.fonts.write() = chain.clone();
+        chain
     }
 
-    /// Format display text from title and artist, handling empty cases
-    fn format_display_text(title: &str, artist: &str) -> String {
-        let primary_artist = Self::get_primary_artist(artist);
-        let title_trimmed = title.trim();
-        let artist_trimmed = primary_artist.trim();
-
-        match (title_trimmed.is_empty(), artist_trimmed.is_empty()) {
-            (true, true) => String::new(),
-            (true, false) => artist_trimmed.to_string(),
-            (false, true) => title_trimmed.to_string(),
-            (false, false) => format!("{} - {}", title_trimmed, artist_trimmed),
+    /// Whether the last icon moves, so it should be redrawn every `ANIMATION_FRAME`
+    pub fn is_animating(&self) -> bool {
+        self.animating.load(Ordering::Relaxed)
+    }
+
+    /// Render the icon for the current app state
+    /// Playing tracks show artwork and text, paused ones the same dimmed (if
+    /// set), loading shows a spinner, anything else the placeholder
+    pub fn render_state(&self, state: &AppState, artwork: &ArtworkCache) -> Result<Vec<u8>> {
+        let display = &state.display_settings;
+        let shows_track = |playback: &PlaybackState| match playback {
+            PlaybackState::Playing => true,
+            PlaybackState::Paused => display.paused == PausedStyle::Dimmed,
+            _ => false,
+        };
+        match &state.current_track {
+            Some(track) if shows_track(&track.state) => {
+                let art = track.image_key.as_deref().and_then(|key| artwork.get(key));
+                let progress = match display.progress_bar {
+                    ProgressBarStyle::Off => None,
+                    style => track.progress().map(|fraction| ProgressBar { fraction, style }),
+                };
+                let zone = state.active_zone_id.as_ref()
+                    .and_then(|id| state.all_zones.iter().find(|z| &z.zone_id == id))
+                    .map(|z| z.display_name.as_str())
+                    .unwrap_or_default();
+                // Settings validate the template, so this only falls back on a bug
+                let template = Template::parse(&display.template).unwrap_or_default();
+                let rendered = template.render(&TemplateFields {
+                    title: &track.title,
+                    artist: &track.artist,
+                    album: &track.album,
+                    zone,
+                    elapsed: track.position(),
+                    length: track.length,
+                    credit_style: display.credits,
+                });
+                self.create_menu_bar_icon(
+                    track.image_key.as_deref().zip(art.as_deref().map(|bytes| bytes.as_slice())),
+                    &rendered.text,
+                    rendered.artist,
+                    progress,
+                    track.state == PlaybackState::Paused,
+                    display,
+                )
+            }
+            Some(track) if track.state == PlaybackState::Loading => self.create_loading_icon(display),
+            // Stopped, no track, or paused with the placeholder style
+            _ => self.create_menu_bar_icon(None, "", None, None, false, display),
         }
     }
 
     /// Create a menu bar icon with album art and text
+    /// `album_art` is the image key and encoded image (JPEG/PNG) from the artwork cache
+    /// `artist` is the byte range of the artist credit in `text`, if any
+    /// `paused` dims the icon and marks it with a pause symbol
     /// Returns PNG bytes
     pub fn create_menu_bar_icon(
         &self,
-        album_art_base64: Option<&str>,
-        title: &str,
-        artist: &str,
+        album_art: Option<(&str, &[u8])>,
+        text: &str,
+        artist: Option<Range<usize>>,
+        progress: Option<ProgressBar>,
+        paused: bool,
+        display: &DisplaySettings,
     ) -> Result<Vec<u8>> {
-        // Render at 3x resolution for sharp Retina text
-        const SCALE_FACTOR: u32 = 3;
-        // Menu bar height is 22pt
-        const MENU_BAR_HEIGHT_PT: u32 = 22;
-        const MAX_CANVAS_WIDTH: u32 = 500 * SCALE_FACTOR;
         const MIN_CANVAS_WIDTH: u32 = MENU_BAR_HEIGHT_PT * SCALE_FACTOR;
         const CANVAS_HEIGHT: u32 = MENU_BAR_HEIGHT_PT * SCALE_FACTOR;
-        const ALBUM_ART_SIZE: u32 = MENU_BAR_HEIGHT_PT * SCALE_FACTOR;
         // Gap between album art and text: 10pt
         const TEXT_GAP_PT: u32 = 10;
-        const TEXT_X_OFFSET: i32 = ((MENU_BAR_HEIGHT_PT + TEXT_GAP_PT) * SCALE_FACTOR) as i32;
         const RIGHT_PADDING: u32 = 3 * SCALE_FACTOR; // Small buffer for glyph overhang
-        // Font size: 21pt at 3x = 63px (original size)
-        const FONT_SIZE_PX: f32 = 63.0;
+        // Text at the default 21pt sits 3px from the top at 3x
+        const DEFAULT_FONT_SIZE_PX: f32 = 63.0;
+        const TEXT_Y_OFFSET: i32 = 3;
+        // Gap after the pause symbol when there's no artwork to put it on: 6pt
+        const PAUSE_GAP_PT: u32 = 6;
+        // Space between the text and the edges of an artwork-coloured pill: 4pt
+        const PILL_PADDING: u32 = 4 * SCALE_FACTOR;
+        // Artwork-coloured underline: 1pt
+        const UNDERLINE_HEIGHT: u32 = SCALE_FACTOR;
+
+        // Theme colours for the macOS appearance (dark/light mode)
+        let dark = is_dark_mode();
+        let mut colors = display.theme.colors(dark);
+
+        // Everything the icon depends on except how far the track has
+        // played, which is added once the bar's width is known. Artwork is
+        // identified by its key, so the image isn't hashed on every redraw
+        let album_art = album_art.map(|(image_key, bytes)| (bytes, hash_of(image_key)));
+        let look_key = hash_of((
+            album_art.map(|(_, hash)| hash),
+            text,
+            &artist,
+            progress.map(|progress| progress.style),
+            paused,
+            (display.font_size.to_bits(), display.max_width, display.layout, display.overflow, display.truncation),
+            &display.fonts,
+            (&display.theme, dark, display.artwork_color),
+        ));
+
+        let max_canvas_width = (display.max_width * SCALE_FACTOR).max(MIN_CANVAS_WIDTH);
+        let font_size_px = display.font_size * SCALE_FACTOR as f32;
+
+        // Artwork-only icons drop the text; text-only icons still show
+        // the placeholder when there is nothing to say
+        let (text, artist) = match display.layout {
+            IconLayout::ArtworkOnly => ("", None),
+            _ => {
+                let leading = text.len() - text.trim_start().len();
+                let artist = artist.map(|range| range.start.saturating_sub(leading)..range.end.saturating_sub(leading));
+                (text.trim(), artist)
+            }
+        };
+        let draw_art = display.layout != IconLayout::TextOnly || text.is_empty();
+
+        // Artwork is decoded up front, as its colours can style the text
+        let artwork = album_art
+            .filter(|_| draw_art || display.artwork_color != ArtworkColor::Off)
+            .map(|(bytes, hash)| self.artwork_image(bytes, hash, ALBUM_ART_SIZE));
+
+        // Pick a colour from the artwork, unless macOS tints the icon: a
+        // readable one for text and underlines, the most prominent for a pill
+        let artwork_color = match artwork.as_ref().and_then(|artwork| artwork.as_ref().ok()) {
+            Some(artwork) if !display.theme.template => match display.artwork_color {
+                ArtworkColor::Off => None,
+                ArtworkColor::Pill => quantize::dominant(&artwork.swatches),
+                ArtworkColor::Text | ArtworkColor::Underline => {
+                    quantize::accent(&artwork.swatches, theme::menu_bar(dark))
+                }
+            },
+            _ => None,
+        };
+        let pill = artwork_color.filter(|_| display.artwork_color == ArtworkColor::Pill && !text.is_empty());
+        let underline = artwork_color.filter(|_| display.artwork_color == ArtworkColor::Underline);
+        let text_color = match (display.artwork_color, artwork_color) {
+            (ArtworkColor::Text, Some(color)) => color.rgba(),
+            (ArtworkColor::Pill, Some(color)) => theme::readable_on(color).rgba(),
+            _ => colors.text,
+        };
+        if let (ArtworkColor::Text | ArtworkColor::Underline, Some(color)) = (display.artwork_color, artwork_color) {
+            colors.accent = color.rgba();
+        }
+
+        let text_x_offset: i32 = if draw_art {
+            ((MENU_BAR_HEIGHT_PT + TEXT_GAP_PT) * SCALE_FACTOR) as i32
+        } else if paused {
+            (PAUSE_SYMBOL_WIDTH + PAUSE_GAP_PT * SCALE_FACTOR) as i32
+        } else {
+            0
+        };
+        // A pill needs room round the text on both sides
+        let (text_x_offset, trailing_padding) = match pill {
+            Some(_) => (text_x_offset + PILL_PADDING as i32, RIGHT_PADDING + PILL_PADDING),
+            None => (text_x_offset, RIGHT_PADDING),
+        };
 
-        // Format display text handling empty title/artist properly
-        let text = Self::format_display_text(title, artist);
+        let fonts = self.font_chain(&display.fonts);
+        let scale = PxScale::from(font_size_px);
+        let shaped = shaping::shape(&fonts, text, scale);
+        let text_width = shaped.width();
 
         // Calculate dynamic canvas width based on text length
         let canvas_width = if !text.is_empty() {
-            let scale = PxScale::from(FONT_SIZE_PX);
-            let text_width = self.measure_text_width(&text, scale);
 
             // Width = album art + spacing + text + padding
             // Use ceiling to ensure we have enough space for the full measured width
-            let required_width = ALBUM_ART_SIZE + (TEXT_X_OFFSET as u32 - ALBUM_ART_SIZE) + text_width.ceil() as u32 + RIGHT_PADDING;
+            let required_width = text_x_offset as u32 + text_width.ceil() as u32 + trailing_padding;
 
             // Cap at maximum width
-            let final_width = required_width.min(MAX_CANVAS_WIDTH);
+            let final_width = required_width.min(max_canvas_width);
 
             log::debug!(
                 "Canvas sizing: text='{}', text_width={:.1}px, required={}, max={}, final={}",
-                text, text_width, required_width, MAX_CANVAS_WIDTH, final_width
+                text, text_width, required_width, max_canvas_width, final_width
             );
 
             final_width
@@ -112,6 +350,60 @@ impl Compositor {
             MIN_CANVAS_WIDTH
         };
 
+        let available_width = canvas_width.saturating_sub(text_x_offset as u32 + trailing_padding);
+
+        // Keep other font sizes centred on the default size's line
+        let text_y = TEXT_Y_OFFSET + ((DEFAULT_FONT_SIZE_PX - font_size_px) / 2.0).round() as i32;
+
+        // Progress bar placement: text-less icons fall back to drawing under
+        // the artwork, artwork-less ones under the text
+        let bar = progress.and_then(|progress| {
+            let under_text = !text.is_empty()
+                && (progress.style == ProgressBarStyle::UnderText || !draw_art);
+            match progress.style {
+                ProgressBarStyle::Off => None,
+                _ if under_text => Some((text_x_offset as u32, available_width, progress.fraction)),
+                _ => Some((0, ALBUM_ART_SIZE, progress.fraction)),
+            }
+        });
+
+        // Progress only changes the icon when the bar moves a whole pixel
+        let filled = bar.map(|(_, width, fraction)| (width as f32 * fraction.clamp(0.0, 1.0)).round() as u32);
+
+        // Scroll overflowing text instead of truncating it, only while playing
+        let scrolling = display.overflow == TextOverflow::Marquee
+            && !paused
+            && text_width.ceil() as u32 > available_width;
+        self.animating.store(scrolling, Ordering::Relaxed);
+
+        // Only icons that don't scroll are cached
+        let icon_key = hash_of((look_key, filled));
+        if !scrolling {
+            if let Some(png) = self.icons.lock().get(&icon_key) {
+                *self.marquee.lock() = None;
+                return Ok(png.clone());
+            }
+        }
+
+        let mut marquee_guard = self.marquee.lock();
+        let mut marquee_frame = None;
+        if scrolling {
+            if marquee_guard.as_ref().map(|m| m.key) != Some(look_key) {
+                let strip = self.render_text_strip(&shaped, text_color, text_y, CANVAS_HEIGHT);
+                *marquee_guard = Some(Marquee { key: look_key, started: Instant::now(), strip, frames: HashMap::new() });
+            }
+
+            if let Some(marquee) = marquee_guard.as_ref() {
+                let offset = marquee.offset(available_width);
+                if let Some(png) = marquee.frames.get(&(offset, filled)) {
+                    return Ok(png.clone());
+                }
+                marquee_frame = Some((offset, filled));
+            }
+        } else {
+            *marquee_guard = None;
+        }
+
         // Create transparent canvas with dynamic width
         let mut canvas = RgbaImage::from_pixel(
             canvas_width,
@@ -120,70 +412,166 @@ impl Compositor {
         );
 
         // Draw album art or placeholder
-        if let Some(artwork_data) = album_art_base64 {
-            if let Ok(art_image) = self.decode_and_resize_artwork(artwork_data, ALBUM_ART_SIZE) {
-                self.overlay_image(&mut canvas, &art_image, 0, 0);
-            } else {
-                // Fallback to colored square if artwork fails
-                self.draw_placeholder_art(&mut canvas, ALBUM_ART_SIZE);
+        if !draw_art {
+            // Text-only layout
+        } else if let Some(artwork) = artwork {
+            match artwork {
+                Ok(artwork) if display.theme.template => {
+                    self.overlay_image(&mut canvas, &template_mask(&artwork.image, dark), 0, 0);
+                }
+                Ok(artwork) => self.overlay_image(&mut canvas, &artwork.image, 0, 0),
+                // Fall back to the placeholder if artwork fails
+                Err(_) => self.draw_placeholder_art(&mut canvas, ALBUM_ART_SIZE, colors.placeholder),
             }
         } else {
             // No artwork provided - draw placeholder
-            self.draw_placeholder_art(&mut canvas, ALBUM_ART_SIZE);
+            self.draw_placeholder_art(&mut canvas, ALBUM_ART_SIZE, colors.placeholder);
+        }
+
+        // The pill runs from just before the text to the right edge
+        if let Some(pill) = pill {
+            let pill_x = text_x_offset as u32 - PILL_PADDING;
+            self.draw_pill(&mut canvas, pill_x, canvas_width - pill_x, pill.rgba());
         }
 
         // Only draw text if we have something to display
-        if !text.is_empty() {
-            let available_width = (canvas_width - TEXT_X_OFFSET as u32 - RIGHT_PADDING) as i32;
-            let scale = PxScale::from(FONT_SIZE_PX);
-            let display_text = self.truncate_text(&text, available_width, scale);
-
-            // Get text color based on macOS appearance (dark/light mode)
-            let text_color = get_text_color();
-
-            // Position text vertically - small offset from top at 3x scale
-            const TEXT_Y_OFFSET: i32 = 3;
-
-            draw_text_mut(
-                &mut canvas,
-                text_color,
-                TEXT_X_OFFSET,
-                TEXT_Y_OFFSET,
-                scale,
-                &self.font,
-                &display_text,
-            );
+        let mut drawn_width = 0;
+        if let (Some((offset, _)), Some(marquee)) = (marquee_frame, marquee_guard.as_ref()) {
+            let visible_width = available_width.min(marquee.strip.width() - offset);
+            let visible = image::imageops::crop_imm(&marquee.strip, offset, 0, visible_width, CANVAS_HEIGHT);
+            self.overlay_image(&mut canvas, &visible.to_image(), i64::from(text_x_offset), 0);
+            drawn_width = available_width;
+        } else if !text.is_empty() {
+            let display_text = if text_width <= available_width as f32 {
+                shaped
+            } else {
+                self.truncate_text(&fonts, text, artist, available_width as f32, scale, display.truncation)
+            };
+
+            display_text.draw(&mut canvas, text_color, text_x_offset, text_y);
+            drawn_width = display_text.width().round() as u32;
+        }
+
+        // Underline along the bottom edge; a progress bar under the text covers it
+        if let Some(underline) = underline.filter(|_| drawn_width > 0) {
+            let x_end = (text_x_offset as u32 + drawn_width).min(canvas_width);
+            for py in CANVAS_HEIGHT - UNDERLINE_HEIGHT..CANVAS_HEIGHT {
+                for px in text_x_offset as u32..x_end {
+                    canvas.put_pixel(px, py, underline.rgba());
+                }
+            }
+        }
+
+        // Draw progress bar under the artwork or the text
+        if let Some((bar_x, bar_width, fraction)) = bar {
+            self.draw_progress_bar(&mut canvas, bar_x, bar_width, fraction, &colors);
+        }
+
+        // Paused: fade everything, then mark the artwork (or the start of the text)
+        if paused {
+            for pixel in canvas.pixels_mut() {
+                pixel[3] = (f32::from(pixel[3]) * PAUSED_OPACITY).round() as u8;
+            }
+            let symbol_x = if draw_art { (ALBUM_ART_SIZE - PAUSE_SYMBOL_WIDTH) / 2 } else { 0 };
+            self.draw_pause_symbol(&mut canvas, symbol_x, text_color);
         }
 
         // Encode as PNG
-        self.encode_png(&canvas)
+        let png = self.encode_png(&canvas)?;
+
+        if let (Some(frame), Some(marquee)) = (marquee_frame, marquee_guard.as_mut()) {
+            if marquee.frames.len() >= MAX_MARQUEE_FRAMES {
+                marquee.frames.clear();
+            }
+            marquee.frames.insert(frame, png.clone());
+        } else if !scrolling {
+            self.icons.lock().insert(icon_key, png.clone());
+        }
+
+        Ok(png)
     }
 
-    /// Decode base64 artwork and resize to target size
-    fn decode_and_resize_artwork(&self, artwork_data: &str, size: u32) -> Result<RgbaImage> {
-        // Strip data URL prefix if present
-        let base64_data = if artwork_data.starts_with("data:") {
-            artwork_data
-                .split(',')
-                .nth(1)
-                .context("Invalid data URL format")?
-        } else {
-            artwork_data
-        };
+    /// Create the loading icon: a spinner whose highlighted spoke moves
+    /// round one step every `ANIMATION_FRAME`
+    /// Returns PNG bytes
+    pub fn create_loading_icon(&self, display: &DisplaySettings) -> Result<Vec<u8>> {
+        self.animating.store(true, Ordering::Relaxed);
+        *self.marquee.lock() = None;
+
+        let frame = (self.started.elapsed().as_millis() / ANIMATION_FRAME.as_millis()) as u64 % SPINNER_SPOKES;
+        let color = display.theme.colors(is_dark_mode()).text;
+        let key = hash_of(("loading", frame, color.0));
+        if let Some(png) = self.icons.lock().get(&key) {
+            return Ok(png.clone());
+        }
 
-        // Check for empty base64 data
-        if base64_data.trim().is_empty() {
-            return Err(anyhow!("Empty base64 data in artwork"));
+        let mut canvas = RgbaImage::from_pixel(ALBUM_ART_SIZE, ALBUM_ART_SIZE, Rgba([0, 0, 0, 0]));
+        self.draw_spinner(&mut canvas, frame, color);
+        let png = self.encode_png(&canvas)?;
+        self.icons.lock().insert(key, png.clone());
+        Ok(png)
+    }
+
+    /// Draw spokes round the canvas centre, brightest at `head` and fading
+    /// behind it in the direction of travel (clockwise)
+    fn draw_spinner(&self, canvas: &mut RgbaImage, head: u64, color: Rgba<u8>) {
+        let size = canvas.width().min(canvas.height()) as f32;
+        let center = size / 2.0;
+        let (inner, outer) = (size * 0.2, size * 0.4);
+        let half_width = size * 0.04;
+
+        for spoke in 0..SPINNER_SPOKES {
+            // Spoke 0 points up
+            let angle = spoke as f32 / SPINNER_SPOKES as f32 * std::f32::consts::TAU;
+            let (dx, dy) = (angle.sin(), -angle.cos());
+            let behind = (head + SPINNER_SPOKES - spoke) % SPINNER_SPOKES;
+            let opacity = 1.0 - 0.75 * behind as f32 / (SPINNER_SPOKES - 1) as f32;
+
+            for py in 0..canvas.height() {
+                for px in 0..canvas.width() {
+                    let (x, y) = (px as f32 + 0.5 - center, py as f32 + 0.5 - center);
+                    // Distance along the spoke, and away from its centre line
+                    let along = x * dx + y * dy;
+                    let across = (x * dy - y * dx).abs();
+                    let outside = (across - half_width).max(inner - along).max(along - outer);
+                    let coverage = (0.5 - outside).clamp(0.0, 1.0);
+                    if coverage > 0.0 {
+                        let alpha = (f32::from(color[3]) * coverage * opacity).round() as u8;
+                        canvas.put_pixel(px, py, Rgba([color[0], color[1], color[2], alpha]));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Render the whole text into a transparent strip for the marquee to scroll through
+    fn render_text_strip(&self, text: &ShapedText, color: Rgba<u8>, y: i32, height: u32) -> RgbaImage {
+        // Room for glyph overhang past the advance width
+        const OVERHANG_PX: u32 = 6;
+
+        let mut strip = RgbaImage::from_pixel(text.width().ceil() as u32 + OVERHANG_PX, height, Rgba([0, 0, 0, 0]));
+        text.draw(&mut strip, color, 0, y);
+        strip
+    }
+
+    /// Decoded artwork at the target size with its palette, from the cache
+    /// when it was drawn recently
+    fn artwork_image(&self, image_bytes: &[u8], hash: u64, size: u32) -> Result<Arc<DecodedArtwork>> {
+        if let Some(artwork) = self.artwork.lock().get(&(hash, size)) {
+            return Ok(artwork.clone());
         }
 
-        // Decode base64
-        use base64::Engine;
-        let image_bytes = base64::engine::general_purpose::STANDARD
-            .decode(base64_data)
-            .context("Failed to decode base64 artwork")?;
+        let image = self.decode_and_resize_artwork(image_bytes, size)?;
+        let swatches = quantize::swatches(&image, ARTWORK_SWATCHES);
+        let artwork = Arc::new(DecodedArtwork { image, swatches });
+        self.artwork.lock().insert((hash, size), artwork.clone());
+        Ok(artwork)
+    }
 
+    /// Decode encoded artwork and resize to target size
+    fn decode_and_resize_artwork(&self, image_bytes: &[u8], size: u32) -> Result<RgbaImage> {
         // Load image
-        let img = image::load_from_memory(&image_bytes)
+        let img = image::load_from_memory(image_bytes)
             .context("Failed to load image from memory")?;
 
         // Validate image dimensions to prevent OOM attacks
@@ -201,15 +589,79 @@ impl Compositor {
         Ok(resized.to_rgba8())
     }
 
+    /// Draw a thin progress bar along the bottom edge of the canvas
+    /// The played part is the accent colour and the unplayed part a faint
+    /// track in the text colour, so the bar's extent stays visible
+    fn draw_progress_bar(&self, canvas: &mut RgbaImage, x: u32, width: u32, fraction: f32, colors: &Colors) {
+        // 1.5pt at 3x scale
+        const BAR_HEIGHT: u32 = 4;
+
+        if width == 0 || canvas.height() < BAR_HEIGHT {
+            return;
+        }
+
+        let color = colors.accent;
+        let track_color = Rgba([colors.text[0], colors.text[1], colors.text[2], 80]);
+        let filled_width = (width as f32 * fraction.clamp(0.0, 1.0)).round() as u32;
+        let y_start = canvas.height() - BAR_HEIGHT;
+        let x_end = (x + width).min(canvas.width());
+
+        for py in y_start..canvas.height() {
+            for px in x..x_end {
+                let pixel_color = if px - x < filled_width { color } else { track_color };
+                canvas.put_pixel(px, py, pixel_color);
+            }
+        }
+    }
+
+    /// Draw a rounded rectangle `width` wide from `x`, nearly the canvas's height
+    fn draw_pill(&self, canvas: &mut RgbaImage, x: u32, width: u32, color: Rgba<u8>) {
+        // 1pt clear above and below, 4pt corners
+        const INSET: f32 = 3.0;
+        const RADIUS: f32 = 12.0;
+
+        let (left, right) = (x as f32, (x + width) as f32);
+        let (top, bottom) = (INSET, canvas.height() as f32 - INSET);
+        let radius = RADIUS.min((right - left) / 2.0).min((bottom - top) / 2.0);
+
+        for py in 0..canvas.height() {
+            for px in x..(x + width).min(canvas.width()) {
+                // Distance outside the rounded rectangle, negative inside
+                let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+                let dx = (left + radius - cx).max(cx - (right - radius)).max(0.0);
+                let dy = (top + radius - cy).max(cy - (bottom - radius)).max(0.0);
+                let outside = (dx * dx + dy * dy).sqrt() - radius;
+                let coverage = (0.5 - outside).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    let alpha = (f32::from(color[3]) * coverage).round() as u8;
+                    canvas.put_pixel(px, py, Rgba([color[0], color[1], color[2], alpha]));
+                }
+            }
+        }
+    }
+
+    /// Draw a pause symbol (two bars) vertically centred, starting at `x`
+    fn draw_pause_symbol(&self, canvas: &mut RgbaImage, x: u32, color: Rgba<u8>) {
+        let top = canvas.height().saturating_sub(PAUSE_SYMBOL_HEIGHT) / 2;
+        let bottom = (top + PAUSE_SYMBOL_HEIGHT).min(canvas.height());
+        let right_bar = x + PAUSE_SYMBOL_WIDTH - PAUSE_BAR_WIDTH;
+
+        for py in top..bottom {
+            for bar_x in [x, right_bar] {
+                for px in bar_x..(bar_x + PAUSE_BAR_WIDTH).min(canvas.width()) {
+                    canvas.put_pixel(px, py, color);
+                }
+            }
+        }
+    }
+
     /// Overlay one image onto another at specified position
     fn overlay_image(&self, canvas: &mut RgbaImage, overlay: &RgbaImage, x: i64, y: i64) {
         image::imageops::overlay(canvas, overlay, x, y);
     }
 
     /// Draw a macaroon silhouette (front view) when no artwork is available
-    /// Adapts to system dark/light mode
-    fn draw_placeholder_art(&self, canvas: &mut RgbaImage, size: u32) {
-        let icon_color = get_text_color();
+    fn draw_placeholder_art(&self, canvas: &mut RgbaImage, size: u32, icon_color: Rgba<u8>) {
         let size_f = size as f32;
         let stroke_width = (size_f * 0.055).max(2.0);
 
@@ -303,67 +755,24 @@ impl Compositor {
         }
     }
 
-    /// Truncate text to fit within available width
-    /// Uses O(n) algorithm by measuring individual glyph advances
-    fn truncate_text(&self, text: &str, max_width: i32, scale: PxScale) -> String {
-        let scaled_font = self.font.as_scaled(scale);
-
-        // First pass: measure full text width
-        let full_width: f32 = text.chars()
-            .map(|ch| scaled_font.h_advance(self.font.glyph_id(ch)))
-            .sum();
-
-        if full_width <= max_width as f32 {
-            return text.to_string();
-        }
-
-        // Calculate ellipsis width
-        let ellipsis = "...";
-        let ellipsis_width: f32 = ellipsis.chars()
-            .map(|ch| scaled_font.h_advance(self.font.glyph_id(ch)))
-            .sum();
-
+    /// Truncate text to fit within available width, returning the line to draw
+    /// Cuts between grapheme clusters, where the truncation setting says
+    fn truncate_text(
+        &self,
+        fonts: &FontChain,
+        text: &str,
+        artist: Option<Range<usize>>,
+        max_width: f32,
+        scale: PxScale,
+        strategy: Truncation,
+    ) -> ShapedText {
         // Add small safety margin for glyph overhang
-        let available_for_text = (max_width as f32 - ellipsis_width - 2.0).max(0.0);
-
-        // If even ellipsis doesn't fit, return empty string
-        if available_for_text <= 0.0 {
-            return String::new();
-        }
-
-        // Second pass: truncate incrementally (O(n) - measure each char once)
-        let mut truncated = String::new();
-        let mut current_width = 0.0;
+        let max_width = max_width - 2.0;
 
-        for ch in text.chars() {
-            let glyph_id = self.font.glyph_id(ch);
-            let char_width = scaled_font.h_advance(glyph_id);
-
-            if current_width + char_width > available_for_text {
-                break;
-            }
-
-            current_width += char_width;
-            truncated.push(ch);
-        }
-
-        // Only add ellipsis if we actually truncated something
-        if truncated.len() < text.len() && !truncated.is_empty() {
-            format!("{}{}", truncated, ellipsis)
-        } else if truncated.is_empty() {
-            ellipsis.to_string()
-        } else {
-            truncated
-        }
-    }
-
-    /// Measure the width of text in pixels
-    fn measure_text_width(&self, text: &str, scale: PxScale) -> f32 {
-        let scaled_font = self.font.as_scaled(scale);
-
-        text.chars()
-            .map(|ch| scaled_font.h_advance(self.font.glyph_id(ch)))
-            .sum()
+        let truncated = truncate::truncate(text, artist, strategy, max_width, |candidate| {
+            shaping::shape(fonts, candidate, scale).width()
+        });
+        shaping::shape(fonts, &truncated, scale)
     }
 
     /// Encode image as PNG bytes
@@ -387,14 +796,99 @@ impl Compositor {
     }
 }
 
+impl Default for Compositor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Create a test icon with fake data (for development testing)
 #[allow(dead_code)]
 pub fn create_test_icon() -> Result<Vec<u8>> {
-    let compositor = Compositor::new()?;
+    let compositor = Compositor::new();
 
     compositor.create_menu_bar_icon(
         None, // No artwork - will show purple placeholder
-        "Test Song Title",
-        "Test Artist Name",
+        "Test Song Title - Test Artist Name",
+        None,
+        None,
+        false,
+        &DisplaySettings::default(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::Color;
+
+    const LONG_TEXT: &str = "A title far too long to fit in the menu bar - Somebody";
+
+    /// A small solid-colour PNG standing in for album art
+    fn artwork() -> Vec<u8> {
+        let image = RgbaImage::from_pixel(64, 64, Rgba([200, 40, 40, 255]));
+        let mut png = Vec::new();
+        image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+        png
+    }
+
+    fn scrolling() -> DisplaySettings {
+        DisplaySettings { max_width: 120, overflow: TextOverflow::Marquee, ..DisplaySettings::default() }
+    }
+
+    fn render(compositor: &Compositor, art: Option<(&str, &[u8])>, style: ProgressBarStyle, display: &DisplaySettings) -> Vec<u8> {
+        let progress = ProgressBar { fraction: 0.5, style };
+        let png = compositor.create_menu_bar_icon(art, LONG_TEXT, None, Some(progress), false, display).unwrap();
+        assert!(compositor.animating.load(Ordering::Relaxed), "text should scroll");
+        png
+    }
+
+    #[test]
+    fn marquee_frames_follow_every_look_change() {
+        let compositor = Compositor::new();
+        let art = artwork();
+        let plain = scrolling();
+
+        let mut placeholder = scrolling();
+        let red = Some(Color([255, 0, 0, 255]));
+        placeholder.theme.light.placeholder = red;
+        placeholder.theme.dark.placeholder = red;
+
+        let mut template = scrolling();
+        template.theme.template = true;
+
+        let underline = DisplaySettings { artwork_color: ArtworkColor::Underline, ..scrolling() };
+        let pill = DisplaySettings { artwork_color: ArtworkColor::Pill, ..scrolling() };
+
+        // Renders close enough together to land on the same marquee frame,
+        // each differing from the one before in a single part of its look
+        let frames = [
+            render(&compositor, None, ProgressBarStyle::UnderText, &plain),
+            render(&compositor, None, ProgressBarStyle::UnderText, &placeholder),
+            render(&compositor, None, ProgressBarStyle::UnderArtwork, &placeholder),
+            render(&compositor, Some(("art", &art)), ProgressBarStyle::UnderArtwork, &placeholder),
+            render(&compositor, Some(("art", &art)), ProgressBarStyle::UnderArtwork, &template),
+            render(&compositor, Some(("art", &art)), ProgressBarStyle::UnderArtwork, &underline),
+            render(&compositor, Some(("art", &art)), ProgressBarStyle::UnderArtwork, &pill),
+        ];
+        for (i, pair) in frames.windows(2).enumerate() {
+            assert!(pair[0] != pair[1], "change {} reused a stale marquee frame", i + 1);
+        }
+    }
+
+    #[test]
+    fn progress_only_changes_the_icon_a_pixel_at_a_time() {
+        let compositor = Compositor::new();
+        let art = artwork();
+        let display = DisplaySettings::default();
+        let render = |fraction: f32| {
+            let progress = ProgressBar { fraction, style: ProgressBarStyle::UnderArtwork };
+            compositor.create_menu_bar_icon(Some(("art", &art)), "Song", None, Some(progress), false, &display).unwrap()
+        };
+
+        // The bar runs under the artwork, so a pixel is 1/ALBUM_ART_SIZE of the track
+        let first = render(0.5);
+        assert!(render(0.503) == first, "a sub-pixel step should reuse the icon");
+        assert!(render(0.5 + 1.0 / ALBUM_ART_SIZE as f32) != first, "a whole pixel step should redraw");
+    }
+}
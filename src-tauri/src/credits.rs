@@ -0,0 +1,214 @@
+//! Artist credit parsing
+//!
+//! Roon joins multiple artists with " / ", and the names themselves often
+//! carry collaboration markers ("feat.", "&", "x", "with"). This splits a
+//! credit into individual names and renders them in the user's chosen style.
+
+use serde::{Deserialize, Serialize};
+
+/// What a separator implies about the names around it
+#[derive(Clone, Copy, PartialEq)]
+enum Separator {
+    /// Always separates two names
+    Plain,
+    /// Introduces featured artists; the closing bracket, if any, ends the name
+    Featuring(Option<char>),
+    /// "&" is part of many band names ("Simon & Garfunkel"), so it only
+    /// separates names in a featured-artist list
+    Ampersand,
+}
+
+/// Separators between artist names, matched case-insensitively
+/// Bracketed forms come first so "A (feat. B)" splits before " feat. " is tried
+const SEPARATORS: &[(&str, Separator)] = &[
+    (" / ", Separator::Plain),
+    ("; ", Separator::Plain),
+    (" (feat. ", Separator::Featuring(Some(')'))),
+    (" (feat ", Separator::Featuring(Some(')'))),
+    (" (ft. ", Separator::Featuring(Some(')'))),
+    (" (with ", Separator::Featuring(Some(')'))),
+    (" [feat. ", Separator::Featuring(Some(']'))),
+    (" feat. ", Separator::Featuring(None)),
+    (" feat ", Separator::Featuring(None)),
+    (" ft. ", Separator::Featuring(None)),
+    (" ft ", Separator::Featuring(None)),
+    (" featuring ", Separator::Featuring(None)),
+    (" with ", Separator::Featuring(None)),
+    (" vs. ", Separator::Plain),
+    (" vs ", Separator::Plain),
+    (" x ", Separator::Plain),
+    (" × ", Separator::Plain),
+    (" & ", Separator::Ampersand),
+];
+
+/// How a multi-artist credit is shown
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CreditStyle {
+    /// "Artist A"
+    #[default]
+    First,
+    /// "Artist A & Artist B"
+    FirstTwo,
+    /// "Artist A, Artist B, Artist C"
+    All,
+    /// "Artist A et al.", or "Artist A & Artist B" when there are only two
+    EtAl,
+}
+
+/// Split a credit string into individual artist names, in order and without duplicates
+pub fn parse_credits<'a>(credit: &'a str) -> Vec<&'a str> {
+    // ASCII lowercasing keeps byte offsets identical to the original
+    let lower = credit.to_ascii_lowercase();
+    let mut names: Vec<&str> = Vec::new();
+    let mut push = |name: &'a str, closing: Option<char>| {
+        let mut name = name.trim();
+        // Drop the bracket closing "(feat. B)", but not brackets in names like "Sunn O)))"
+        if let Some(close) = closing {
+            if let Some(stripped) = name.strip_suffix(close) {
+                name = stripped.trim_end();
+            }
+        }
+        let lowercase = |text: &str| text.chars().flat_map(char::to_lowercase).collect::<String>();
+        if !name.is_empty() && !names.iter().any(|n| lowercase(n) == lowercase(name)) {
+            names.push(name);
+        }
+    };
+
+    let mut start = 0;
+    let mut index = 0;
+    let mut featuring = false;
+    let mut closing = None;
+    while let Some(ch) = credit[index..].chars().next() {
+        let separator = SEPARATORS.iter().find(|(pattern, kind)| {
+            lower[index..].starts_with(pattern) && (featuring || *kind != Separator::Ampersand)
+        });
+
+        match separator {
+            Some((pattern, kind)) => {
+                push(&credit[start..index], closing);
+                match kind {
+                    Separator::Featuring(close) => {
+                        featuring = true;
+                        closing = *close;
+                    }
+                    // A plain separator starts the next main artist, whose
+                    // name may contain "&" again
+                    Separator::Plain => {
+                        featuring = false;
+                        closing = None;
+                    }
+                    Separator::Ampersand => {}
+                }
+                index += pattern.len();
+                start = index;
+            }
+            None => index += ch.len_utf8(),
+        }
+    }
+    push(&credit[start..], closing);
+
+    names
+}
+
+/// The first artist of a credit, or "" if there is none
+pub fn primary_artist(credit: &str) -> &str {
+    parse_credits(credit).first().copied().unwrap_or_default()
+}
+
+/// Render a credit in the given style
+pub fn format_credits(credit: &str, style: CreditStyle) -> String {
+    let names = parse_credits(credit);
+    match (style, names.as_slice()) {
+        (_, []) => String::new(),
+        (_, [only]) => only.to_string(),
+        (CreditStyle::First, [first, ..]) => first.to_string(),
+        (CreditStyle::FirstTwo | CreditStyle::EtAl, [first, second]) => format!("{} & {}", first, second),
+        (CreditStyle::FirstTwo, [first, second, ..]) => format!("{} & {}", first, second),
+        (CreditStyle::All, _) => names.join(", "),
+        (CreditStyle::EtAl, [first, ..]) => format!("{} et al.", first),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Credits as Roon reports them, and the names they should split into
+    const CORPUS: &[(&str, &[&str])] = &[
+        ("Radiohead", &["Radiohead"]),
+        ("Daft Punk / Pharrell Williams / Nile Rodgers", &["Daft Punk", "Pharrell Williams", "Nile Rodgers"]),
+        ("Kendrick Lamar; SZA", &["Kendrick Lamar", "SZA"]),
+        ("Calvin Harris (feat. Rihanna)", &["Calvin Harris", "Rihanna"]),
+        ("Calvin Harris (feat Rihanna)", &["Calvin Harris", "Rihanna"]),
+        ("Mark Ronson [feat. Bruno Mars]", &["Mark Ronson", "Bruno Mars"]),
+        ("Santana feat. Rob Thomas", &["Santana", "Rob Thomas"]),
+        ("Gorillaz ft. De La Soul", &["Gorillaz", "De La Soul"]),
+        ("Massive Attack featuring Elizabeth Fraser", &["Massive Attack", "Elizabeth Fraser"]),
+        ("Major Lazer feat. Justin Bieber & MØ", &["Major Lazer", "Justin Bieber", "MØ"]),
+        ("Mumford & Sons feat. Baaba Maal & The Very Best", &["Mumford & Sons", "Baaba Maal", "The Very Best"]),
+        ("Simon & Garfunkel", &["Simon & Garfunkel"]),
+        ("Earth, Wind & Fire", &["Earth, Wind & Fire"]),
+        ("Nick Cave & The Bad Seeds / Kylie Minogue", &["Nick Cave & The Bad Seeds", "Kylie Minogue"]),
+        ("Sunn O)))", &["Sunn O)))"]),
+        ("Sunn O))) (feat. Scott Walker)", &["Sunn O)))", "Scott Walker"]),
+        ("Boris (feat. Sunn O))))", &["Boris", "Sunn O)))"]),
+        ("Skrillex x Diplo", &["Skrillex", "Diplo"]),
+        ("Armin van Buuren vs. Vini Vici", &["Armin van Buuren", "Vini Vici"]),
+        ("Dimitri Vegas & Like Mike vs Diplo", &["Dimitri Vegas & Like Mike", "Diplo"]),
+        ("Skepta × Nafe Smallz", &["Skepta", "Nafe Smallz"]),
+        ("Calvin Harris FEAT. Rihanna", &["Calvin Harris", "Rihanna"]),
+        // "&" after a bracketed feature belongs to the next main artist again
+        ("Paul Simon (feat. Ladysmith Black Mambazo) / Simon & Garfunkel", &["Paul Simon", "Ladysmith Black Mambazo", "Simon & Garfunkel"]),
+        ("Sigur Rós / Jónsi", &["Sigur Rós", "Jónsi"]),
+        ("Queen / David Bowie / queen", &["Queen", "David Bowie"]),
+        ("Björk / BJÖRK", &["Björk"]),
+        ("Xavier Rudd", &["Xavier Rudd"]),
+        ("", &[]),
+        ("   ", &[]),
+        (" / ", &[]),
+        ("Radiohead / ", &["Radiohead"]),
+    ];
+
+    #[test]
+    fn splits_real_credits() {
+        for (credit, expected) in CORPUS {
+            assert_eq!(parse_credits(credit), *expected, "credit {:?}", credit);
+        }
+    }
+
+    #[test]
+    fn primary_artist_is_the_first_name() {
+        assert_eq!(primary_artist("Santana feat. Rob Thomas"), "Santana");
+        assert_eq!(primary_artist("Simon & Garfunkel"), "Simon & Garfunkel");
+        assert_eq!(primary_artist("  "), "");
+    }
+
+    #[test]
+    fn styles_for_each_number_of_names() {
+        let one = "A";
+        let two = "A / B";
+        let three = "A / B / C";
+        let cases = [
+            (CreditStyle::First, ["A", "A", "A"]),
+            (CreditStyle::FirstTwo, ["A", "A & B", "A & B"]),
+            (CreditStyle::All, ["A", "A, B", "A, B, C"]),
+            (CreditStyle::EtAl, ["A", "A & B", "A et al."]),
+        ];
+        for (style, expected) in cases {
+            let formatted = [one, two, three].map(|credit| format_credits(credit, style));
+            assert_eq!(formatted, expected, "{:?}", style);
+        }
+
+        assert_eq!(format_credits("A / B / C / D", CreditStyle::EtAl), "A et al.");
+        assert_eq!(format_credits("A / B / C / D", CreditStyle::All), "A, B, C, D");
+    }
+
+    #[test]
+    fn empty_credits_format_as_nothing() {
+        for style in [CreditStyle::First, CreditStyle::FirstTwo, CreditStyle::All, CreditStyle::EtAl] {
+            assert_eq!(format_credits("", style), "");
+            assert_eq!(format_credits(" \t ", style), "");
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Font discovery and per-character fallback
+//!
+//! Each character is drawn with the first font in the chain that has a glyph
+//! for it: `MACAROON_FONT`, the configured fonts, the system UI font and its
+//! platform fallbacks, the embedded DejaVu Sans and finally whatever
+//! fontconfig suggests for that character. Fonts are only read from disk
+//! once a character needs them.
+
+use ab_glyph::{Font, FontVec, GlyphId};
+use anyhow::{anyhow, Context, Result};
+use parking_lot::Mutex;
+use unicode_properties::{GeneralCategoryGroup, UnicodeGeneralCategory};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Last-resort font, covering Latin, Greek, Cyrillic, Arabic and Hebrew
+const EMBEDDED_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// SF Pro followed by the system fonts for scripts it doesn't cover
+#[cfg(target_os = "macos")]
+const SYSTEM_FONTS: &[&str] = &[
+    "/System/Library/Fonts/SFNS.ttf",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/AppleSDGothicNeo.ttc",
+    "/System/Library/Fonts/GeezaPro.ttc",
+    "/System/Library/Fonts/Apple Color Emoji.ttc",
+    "/System/Library/Fonts/Apple Symbols.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+];
+
+/// Directories searched for a configured family name
+#[cfg(target_os = "macos")]
+const FONT_DIRS: &[&str] = &[
+    "/System/Library/Fonts",
+    "/System/Library/Fonts/Supplemental",
+    "/Library/Fonts",
+    "~/Library/Fonts",
+];
+
+/// Where a font in the chain comes from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FontSource {
+    /// A font file, with the face index inside a collection (.ttc)
+    File { path: PathBuf, index: u32 },
+    Embedded,
+}
+
+impl FontSource {
+    fn load(&self) -> Result<FontVec> {
+        match self {
+            Self::File { path, index } => {
+                let data = std::fs::read(path)
+                    .with_context(|| format!("Failed to read font {:?}", path))?;
+                FontVec::try_from_vec_and_index(data, *index)
+                    .map_err(|_| anyhow!("Failed to parse font data in {:?}", path))
+            }
+            Self::Embedded => FontVec::try_from_vec(EMBEDDED_FONT.to_vec())
+                .map_err(|_| anyhow!("Failed to parse embedded font")),
+        }
+    }
+}
+
+/// Find a configured font: a file path (`~/` allowed) or a family name
+pub fn resolve(entry: &str) -> Option<FontSource> {
+    let entry = entry.trim();
+    if entry.is_empty() {
+        return None;
+    }
+
+    if entry.contains('/') {
+        let path = expand_home(entry);
+        return path.is_file().then_some(FontSource::File { path, index: 0 });
+    }
+
+    find_family(entry)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Look for a font file named after the family, e.g. "Helvetica Neue" -> "HelveticaNeue.ttc"
+#[cfg(target_os = "macos")]
+fn find_family(family: &str) -> Option<FontSource> {
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let wanted = normalize(family);
+
+    FONT_DIRS.iter()
+        .filter_map(|dir| std::fs::read_dir(expand_home(dir)).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|entry| entry.path())
+        .find(|path| {
+            let is_font = path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext.to_ascii_lowercase().as_str(), "ttf" | "otf" | "ttc"));
+            is_font && path.file_stem().and_then(|stem| stem.to_str()).is_some_and(|stem| normalize(stem) == wanted)
+        })
+        .map(|path| FontSource::File { path, index: 0 })
+}
+
+/// Ask fontconfig for the family, rejecting its substitutes
+#[cfg(not(target_os = "macos"))]
+fn find_family(family: &str) -> Option<FontSource> {
+    let output = fontconfig_match(family, "%{family}\t%{file}\t%{index}")?;
+    let mut fields = output.splitn(3, '\t');
+    let families = fields.next()?;
+    if !families.split(',').any(|name| name.trim().eq_ignore_ascii_case(family)) {
+        return None;
+    }
+    parse_fontconfig_file(fields.next()?, fields.next()?)
+}
+
+/// The system UI font and its fallbacks, most preferred first
+#[cfg(target_os = "macos")]
+fn system_fonts() -> Vec<FontSource> {
+    SYSTEM_FONTS.iter()
+        .map(|path| FontSource::File { path: PathBuf::from(path), index: 0 })
+        .collect()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn system_fonts() -> Vec<FontSource> {
+    fontconfig_match("sans-serif", "%{file}\t%{index}")
+        .and_then(|output| {
+            let (file, index) = output.split_once('\t')?;
+            parse_fontconfig_file(file, index)
+        })
+        .into_iter()
+        .collect()
+}
+
+/// A font that fontconfig says covers the character
+#[cfg(not(target_os = "macos"))]
+fn system_font_for(ch: char) -> Option<FontSource> {
+    let output = fontconfig_match(&format!("sans-serif:charset={:x}", u32::from(ch)), "%{file}\t%{index}")?;
+    let (file, index) = output.split_once('\t')?;
+    parse_fontconfig_file(file, index)
+}
+
+/// The macOS list above is already the whole chain
+#[cfg(target_os = "macos")]
+fn system_font_for(_ch: char) -> Option<FontSource> {
+    None
+}
+
+/// Run `fc-match` with an output format, returning None if fontconfig isn't installed
+#[cfg(not(target_os = "macos"))]
+fn fontconfig_match(pattern: &str, format: &str) -> Option<String> {
+    let output = std::process::Command::new("fc-match")
+        .args(["--format", format, pattern])
+        .output()
+        .map_err(|e| log::debug!("fc-match unavailable: {}", e))
+        .ok()?;
+    output.status.success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn parse_fontconfig_file(file: &str, index: &str) -> Option<FontSource> {
+    let path = PathBuf::from(file.trim());
+    path.is_file().then(|| FontSource::File {
+        path,
+        index: index.trim().parse().unwrap_or(0),
+    })
+}
+
+/// Zero-width characters that belong to whatever surrounds them
+fn is_invisible(ch: char) -> bool {
+    ch.is_control()
+        || matches!(ch, '\u{200B}'..='\u{200F}' | '\u{2060}'..='\u{2064}' | '\u{FE00}'..='\u{FE0F}' | '\u{FEFF}')
+}
+
+/// A font in the chain, kept with its face index so it can be shaped
+pub struct LoadedFont {
+    pub font: FontVec,
+    index: u32,
+}
+
+impl LoadedFont {
+    fn covers(&self, ch: char) -> bool {
+        self.font.glyph_id(ch) != GlyphId(0)
+    }
+
+    /// The font as rustybuzz sees it, borrowing the same data
+    pub fn shaping_face(&self) -> Option<rustybuzz::Face<'_>> {
+        rustybuzz::Face::from_slice(self.font.as_slice(), self.index)
+    }
+}
+
+/// Loaded fonts and the ones still to try
+struct Faces {
+    loaded: Vec<Arc<LoadedFont>>,
+    candidates: VecDeque<FontSource>,
+    tried: HashSet<FontSource>,
+    /// Index into `loaded` for every character looked up so far
+    by_char: HashMap<char, usize>,
+}
+
+impl Faces {
+    /// The font that draws `ch`, or the primary font (showing .notdef) if none does
+    fn index_for(&mut self, ch: char) -> usize {
+        if let Some(&index) = self.by_char.get(&ch) {
+            return index;
+        }
+        let index = self.find(ch).unwrap_or_else(|| {
+            log::debug!("No font has a glyph for {:?}", ch);
+            0
+        });
+        self.by_char.insert(ch, index);
+        index
+    }
+
+    fn find(&mut self, ch: char) -> Option<usize> {
+        if let Some(index) = self.loaded.iter().position(|font| font.covers(ch)) {
+            return Some(index);
+        }
+        while let Some(source) = self.candidates.pop_front() {
+            if let Some(index) = self.load(source) {
+                if self.loaded[index].covers(ch) {
+                    return Some(index);
+                }
+            }
+        }
+        let index = self.load(system_font_for(ch)?)?;
+        self.loaded[index].covers(ch).then_some(index)
+    }
+
+    /// Load a font into the chain, at most once per source
+    fn load(&mut self, source: FontSource) -> Option<usize> {
+        if !self.tried.insert(source.clone()) {
+            return None;
+        }
+        let index = match source {
+            FontSource::File { index, .. } => index,
+            FontSource::Embedded => 0,
+        };
+        match source.load() {
+            Ok(font) => {
+                log::info!("Loaded font {:?}", source);
+                self.loaded.push(Arc::new(LoadedFont { font, index }));
+                Some(self.loaded.len() - 1)
+            }
+            Err(e) => {
+                log::debug!("Skipping font: {:#}", e);
+                None
+            }
+        }
+    }
+}
+
+/// A stretch of text drawn with one font
+pub struct FontRun {
+    /// Byte range in the text that was split
+    pub range: Range<usize>,
+    pub font: Arc<LoadedFont>,
+}
+
+/// Fonts used to measure and draw text, in fallback order
+pub struct FontChain {
+    /// The `display.fonts` setting the chain was built from
+    configured: Vec<String>,
+    faces: Mutex<Faces>,
+}
+
+impl FontChain {
+    pub fn new(configured: &[String]) -> Self {
+        let override_font = std::env::var("MACAROON_FONT").ok();
+        let mut candidates: VecDeque<FontSource> = override_font.iter()
+            .chain(configured)
+            .filter_map(|entry| {
+                let source = resolve(entry);
+                if source.is_none() {
+                    log::warn!("Font {:?} not found", entry);
+                }
+                source
+            })
+            .collect();
+        candidates.extend(system_fonts());
+        candidates.push_back(FontSource::Embedded);
+
+        let mut faces = Faces {
+            loaded: Vec::new(),
+            candidates,
+            tried: HashSet::new(),
+            by_char: HashMap::new(),
+        };
+
+        // The first font that loads sets the baseline; the embedded one always does
+        while faces.loaded.is_empty() {
+            let source = faces.candidates.pop_front().expect("the embedded font is always a candidate");
+            faces.load(source);
+        }
+
+        Self {
+            configured: configured.to_vec(),
+            faces: Mutex::new(faces),
+        }
+    }
+
+    pub fn configured(&self) -> &[String] {
+        &self.configured
+    }
+
+    /// The first font in the chain, whose metrics set the baseline
+    pub fn primary(&self) -> Arc<LoadedFont> {
+        self.faces.lock().loaded[0].clone()
+    }
+
+    /// Split text into runs that each use a single font
+    /// Combining marks and joiners stay with the character before them when
+    /// its font has them, so they can be shaped together
+    pub fn runs(&self, text: &str) -> Vec<FontRun> {
+        let mut faces = self.faces.lock();
+        let mut runs: Vec<(Range<usize>, usize)> = Vec::new();
+
+        for (start, ch) in text.char_indices() {
+            let current = runs.last().map(|(_, font)| *font);
+            let is_mark = ch.general_category_group() == GeneralCategoryGroup::Mark;
+            let font = match current {
+                Some(font) if is_invisible(ch) || (is_mark && faces.loaded[font].covers(ch)) => font,
+                None if is_invisible(ch) => 0,
+                _ => faces.index_for(ch),
+            };
+
+            let end = start + ch.len_utf8();
+            match runs.last_mut() {
+                Some((range, current)) if *current == font => range.end = end,
+                _ => runs.push((start..end, font)),
+            }
+        }
+
+        runs.into_iter()
+            .map(|(range, font)| FontRun { range, font: faces.loaded[font].clone() })
+            .collect()
+    }
+}
@@ -0,0 +1,524 @@
+use anyhow::{Context, Result};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Runtime};
+
+use crate::artwork::ArtworkCache;
+use crate::source::{SharedSource, SourceEvent};
+use crate::state::SharedState;
+use crate::tray::TrayManager;
+use crate::types::{
+    capability, AppState, ConnectionStatus, NowPlayingData, PlaybackState, SidecarCommand,
+    SidecarMessage, Zone, ZonePreference,
+};
+
+/// UI work needed after an event has been applied to the state
+#[derive(Debug, Default)]
+pub struct Effects {
+    pub rebuild_menu: bool,
+    pub update_icon: bool,
+    /// Artwork the state refers to but the cache doesn't have
+    pub fetch_artwork: Option<String>,
+}
+
+/// Spawn the thread that applies source events to the state and refreshes the tray
+/// Runs until every sender of `events` is dropped
+pub fn spawn_dispatcher<R: Runtime>(
+    app: AppHandle<R>,
+    state: SharedState,
+    artwork: Arc<ArtworkCache>,
+    source: SharedSource,
+    events: Receiver<SourceEvent>,
+) {
+    let result = thread::Builder::new()
+        .name("source-events".to_string())
+        .spawn(move || {
+            for event in events {
+                let effects = match apply_event(event, &state, &artwork) {
+                    Ok(effects) => effects,
+                    Err(e) => {
+                        log::error!("Error handling {} event: {}", source.name(), e);
+                        continue;
+                    }
+                };
+
+                if let Some(image_key) = effects.fetch_artwork {
+                    request_artwork(&source, image_key);
+                }
+
+                if !effects.rebuild_menu && !effects.update_icon {
+                    continue;
+                }
+
+                // Must run on main thread for macOS compatibility
+                let app_clone = app.clone();
+                let state_clone = state.clone();
+                if let Err(e) = app.run_on_main_thread(move || {
+                    if effects.rebuild_menu {
+                        if let Err(e) = TrayManager::rebuild_menu(&app_clone, &state_clone) {
+                            log::error!("Failed to rebuild menu: {}", e);
+                        }
+                    }
+                    if effects.update_icon {
+                        if let Err(e) = TrayManager::update_icon(&app_clone, &state_clone) {
+                            log::error!("Failed to update icon: {}", e);
+                        }
+                    }
+                }) {
+                    log::error!("Failed to dispatch tray update to main thread: {}", e);
+                }
+            }
+
+            log::debug!("Source event channel closed");
+        });
+
+    if let Err(e) = result {
+        log::error!("Failed to spawn source event thread: {}", e);
+    }
+}
+
+/// Ask the source to re-send artwork the cache doesn't have
+pub fn request_artwork(source: &SharedSource, image_key: String) {
+    if !source.has_capability(capability::FETCH_ARTWORK) {
+        return;
+    }
+
+    let key_for_log = image_key.clone();
+    if let Err(e) = source.send_command(SidecarCommand::FetchArtwork { image_key }) {
+        log::warn!("Failed to request artwork {}: {}", key_for_log, e);
+    }
+}
+
+/// Count a source message that was dropped, returning the new total
+fn record_skipped_message(state: &SharedState) -> u64 {
+    let mut state_guard = state.write();
+    state_guard.skipped_messages = state_guard.skipped_messages.saturating_add(1);
+    state_guard.skipped_messages
+}
+
+/// Check if zones have meaningfully changed (number, IDs, names, or states)
+fn zones_changed(old_zones: &[Zone], new_zones: &[Zone]) -> bool {
+    // Different number of zones
+    if old_zones.len() != new_zones.len() {
+        return true;
+    }
+
+    // Check each zone
+    for new_zone in new_zones {
+        match old_zones.iter().find(|z| z.zone_id == new_zone.zone_id) {
+            None => return true, // New zone appeared
+            Some(old_zone) => {
+                // Check if display name or state changed
+                if old_zone.display_name != new_zone.display_name || old_zone.state != new_zone.state {
+                    return true;
+                }
+            }
+        }
+    }
+
+    // Check if any old zones disappeared
+    for old_zone in old_zones {
+        if !new_zones.iter().any(|z| z.zone_id == old_zone.zone_id) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Apply a new zone preference chosen by the user, showing a selected zone straight away
+pub fn select_zone(state: &mut AppState, preference: ZonePreference) {
+    // Reset smart-switch state since user explicitly selected a zone
+    state.is_smart_switched = false;
+    state.preferred_zone_stopped_at = None;
+
+    let zone_id = match &preference {
+        ZonePreference::Selected { zone_id, .. } => zone_id.clone(),
+        ZonePreference::Auto => {
+            // Keep showing the current zone until auto selection picks another
+            state.zone_preference = preference;
+            return;
+        }
+    };
+    state.zone_preference = preference;
+
+    // Load the selected zone's now_playing data
+    let zone_data = state.all_zones.iter()
+        .find(|z| z.zone_id == zone_id)
+        .map(|z| (z.now_playing.clone(), z.display_name.clone()));
+
+    if let Some((now_playing, display_name)) = zone_data {
+        // Only update current_track if the zone has data
+        // Keep existing track if zone is playing but data hasn't arrived yet
+        if now_playing.is_some() {
+            state.current_track = now_playing;
+        }
+        log::info!("Selected zone: {}", display_name);
+    } else {
+        // Zone doesn't exist in our list - this shouldn't normally happen
+        // Keep existing track to avoid flicker
+        log::warn!("Selected zone not found in zone list: {}", zone_id);
+    }
+
+    // Always update active zone ID - the track data will arrive shortly
+    state.active_zone_id = Some(zone_id);
+}
+
+/// Change the selected zone's smart switching settings without selecting it
+/// again, so a zone being followed stays followed and the grace period keeps
+/// counting. Returns true if the displayed zone changed
+pub fn update_smart_switching(state: &mut AppState, preference: ZonePreference) -> bool {
+    state.zone_preference = preference;
+
+    if let ZonePreference::Selected { zone_id, smart_switching: false, .. } = &state.zone_preference {
+        state.preferred_zone_stopped_at = None;
+        if state.is_smart_switched {
+            log::info!("Smart switching turned off, returning to selected zone {}", zone_id);
+            state.is_smart_switched = false;
+            let zone_id = zone_id.clone();
+            show_zone(state, zone_id);
+            return true;
+        }
+        return false;
+    }
+
+    // A shorter grace period may already have run out
+    update_smart_switch(state, Instant::now())
+}
+
+/// Show a zone's track in place of the current one
+fn show_zone(state: &mut AppState, zone_id: String) {
+    state.current_track = state.all_zones.iter()
+        .find(|z| z.zone_id == zone_id)
+        .and_then(|z| z.now_playing.clone());
+    state.active_zone_id = Some(zone_id);
+}
+
+/// Smart switching: once the selected zone has been idle for its grace period,
+/// follow another playing zone, and snap back as soon as the selected zone plays again.
+/// Returns true if the displayed zone changed
+fn update_smart_switch(state: &mut AppState, now: Instant) -> bool {
+    let (preferred_id, grace_period) = match &state.zone_preference {
+        ZonePreference::Selected { zone_id, smart_switching: true, grace_period_mins } => {
+            (zone_id.clone(), Duration::from_secs(u64::from(*grace_period_mins) * 60))
+        }
+        _ => return false,
+    };
+
+    let preferred_active = state.all_zones.iter().any(|z| {
+        z.zone_id == preferred_id && matches!(z.state, PlaybackState::Playing | PlaybackState::Loading)
+    });
+
+    let follow = if preferred_active {
+        state.preferred_zone_stopped_at = None;
+        None
+    } else {
+        let stopped_at = *state.preferred_zone_stopped_at.get_or_insert(now);
+        if now.duration_since(stopped_at) >= grace_period {
+            // Stay on the zone being followed while it plays, otherwise take the first playing one
+            let active_id = state.active_zone_id.as_deref();
+            state.all_zones.iter()
+                .filter(|z| z.zone_id != preferred_id && z.state == PlaybackState::Playing)
+                .min_by_key(|z| Some(z.zone_id.as_str()) != active_id)
+                .map(|z| z.zone_id.clone())
+        } else {
+            None
+        }
+    };
+
+    match follow {
+        Some(zone_id) => {
+            if state.is_smart_switched && state.active_zone_id.as_deref() == Some(zone_id.as_str()) {
+                return false;
+            }
+            log::info!("Selected zone {} is idle, following {}", preferred_id, zone_id);
+            state.is_smart_switched = true;
+            show_zone(state, zone_id);
+        }
+        None => {
+            if !state.is_smart_switched {
+                return false;
+            }
+            log::info!("Returning to selected zone {}", preferred_id);
+            state.is_smart_switched = false;
+            show_zone(state, preferred_id);
+        }
+    }
+
+    true
+}
+
+/// Re-check time-based state between source updates (the smart switching grace period)
+pub fn apply_tick(state: &SharedState) -> Effects {
+    let switched = update_smart_switch(&mut state.write(), Instant::now());
+    Effects { rebuild_menu: switched, update_icon: switched, ..Effects::default() }
+}
+
+/// Apply a source event to the app state
+/// Returns the UI work needed to reflect the change
+pub fn apply_event(event: SourceEvent, state: &SharedState, artwork: &ArtworkCache) -> Result<Effects> {
+    match event {
+        SourceEvent::Message(message) => apply_message(message, state, artwork),
+        SourceEvent::Status(status) => {
+            log::info!("Source status: {:?}", status);
+
+            let mut state_guard = state.write();
+            // An incompatible source will not send anything else, so clear what it sent
+            if matches!(status, ConnectionStatus::Incompatible(_)) {
+                state_guard.current_track = None;
+                state_guard.active_zone_id = None;
+            }
+            state_guard.connection_status = status;
+
+            Ok(Effects { rebuild_menu: true, update_icon: true, ..Effects::default() })
+        }
+        SourceEvent::Disconnected => {
+            log::info!("Received disconnection signal from source");
+            let mut state_guard = state.write();
+            state_guard.current_track = None;
+            state_guard.active_zone_id = None;
+
+            Ok(Effects { update_icon: true, ..Effects::default() })
+        }
+        SourceEvent::CapabilitiesChanged => {
+            Ok(Effects { rebuild_menu: true, ..Effects::default() })
+        }
+    }
+}
+
+/// Apply a message in the shared format to the app state
+fn apply_message(message: SidecarMessage, state: &SharedState, artwork: &ArtworkCache) -> Result<Effects> {
+    let mut effects = Effects::default();
+
+    match message {
+        SidecarMessage::Hello { protocol_version, .. } => {
+            log::warn!("Ignoring repeated handshake from source (protocol v{})", protocol_version);
+        }
+        SidecarMessage::NowPlaying {
+            zone_id,
+            title,
+            artist,
+            album,
+            state: playback_state,
+            image_key,
+            seek_position,
+            length,
+        } => {
+            log::debug!("Now playing in zone {}: {} - {} ({:?})", zone_id, title, artist, playback_state);
+
+            // Update app state
+            let track_data = NowPlayingData {
+                title,
+                artist,
+                album,
+                state: playback_state,
+                image_key,
+                seek_position,
+                length,
+                position_updated_at: Instant::now(),
+            };
+
+            // Artwork is normally sent before the first now_playing that uses it;
+            // if the cache lost it, ask the source to send it again
+            if let Some(key) = &track_data.image_key {
+                if artwork.get(key).is_none() {
+                    effects.fetch_artwork = Some(key.clone());
+                }
+            }
+
+            // Update state - only update current_track if this is the selected zone
+            let mut state_guard = state.write();
+
+            // Always update the specific zone's now_playing data
+            if let Some(zone) = state_guard.all_zones.iter_mut().find(|z| z.zone_id == zone_id) {
+                zone.now_playing = Some(track_data.clone());
+                zone.state_changed_at = Instant::now();
+            }
+
+            // Check if this zone is the one we should display
+            let is_selected_zone = match &state_guard.zone_preference {
+                ZonePreference::Auto => {
+                    // In Auto mode:
+                    // 1. If we already have an active zone showing this content, keep showing it
+                    // 2. If no active zone, prefer a playing zone over just any zone
+                    if state_guard.active_zone_id.as_ref() == Some(&zone_id) {
+                        true
+                    } else if state_guard.active_zone_id.is_none() {
+                        // Only auto-select if this zone is actually playing
+                        // This prevents showing the first paused/stopped zone arbitrarily
+                        track_data.state == PlaybackState::Playing
+                    } else {
+                        false
+                    }
+                }
+                ZonePreference::Selected { zone_id: selected_id, .. } => {
+                    // While smart switching follows another zone, that zone is the one shown
+                    if state_guard.is_smart_switched {
+                        state_guard.active_zone_id.as_ref() == Some(&zone_id)
+                    } else {
+                        selected_id == &zone_id
+                    }
+                }
+            };
+
+            // Only update tray icon if this was the selected zone
+            if is_selected_zone {
+                state_guard.current_track = Some(track_data);
+                state_guard.active_zone_id = Some(zone_id);
+                effects.update_icon = true;
+            }
+        }
+        SidecarMessage::ZoneList { zones } => {
+            log::debug!("Zone list received: {} zones", zones.len());
+
+            let mut state_guard = state.write();
+
+            // Convert ZoneInfo to Zone
+            let now = Instant::now();
+            let new_zones: Vec<Zone> = zones.into_iter().map(|zone_info| {
+                // Find existing zone to preserve state_changed_at
+                let state_changed_at = state_guard.all_zones
+                    .iter()
+                    .find(|z| z.zone_id == zone_info.zone_id)
+                    .map(|z| z.state_changed_at)
+                    .unwrap_or(now);
+
+                let state_clone = zone_info.state.clone();
+                Zone {
+                    zone_id: zone_info.zone_id,
+                    display_name: zone_info.display_name,
+                    state: zone_info.state,
+                    now_playing: zone_info.now_playing.map(|np| NowPlayingData {
+                        title: np.title,
+                        artist: np.artist,
+                        album: np.album,
+                        state: state_clone.clone(),
+                        image_key: np.image_key,
+                        seek_position: np.seek_position,
+                        length: np.length,
+                        position_updated_at: now,
+                    }),
+                    state_changed_at,
+                }
+            }).collect();
+
+            // Check if zones actually changed (to avoid unnecessary rebuilds)
+            let zones_changed = zones_changed(&state_guard.all_zones, &new_zones);
+
+            // Check if active zone changed to stopped/paused - need to update icon
+            let needs_icon_update = if let Some(active_id) = &state_guard.active_zone_id {
+                if let Some(new_zone) = new_zones.iter().find(|z| &z.zone_id == active_id) {
+                    // Update current_track state from zone list
+                    if let Some(ref mut current) = state_guard.current_track {
+                        if current.state != new_zone.state {
+                            current.set_state(new_zone.state.clone());
+                            true
+                        } else {
+                            false
+                        }
+                    } else {
+                        false
+                    }
+                } else {
+                    false
+                }
+            } else {
+                false
+            };
+
+            state_guard.all_zones = new_zones;
+
+            // Zone states changed, so the selected zone may have stopped or resumed
+            let switched = update_smart_switch(&mut state_guard, now);
+
+            // Determine if we need to rebuild the menu
+            // Use simple debounce: rebuild if zones changed and 1 second has passed
+            let needs_rebuild = if switched {
+                // The "following" marker moved
+                true
+            } else if zones_changed {
+                match state_guard.last_menu_rebuild {
+                    None => true, // First rebuild ever
+                    Some(last_rebuild) => last_rebuild.elapsed().as_secs() >= 1,
+                }
+            } else {
+                false
+            };
+
+            // Update last_menu_rebuild timestamp atomically with the decision
+            // This prevents race conditions where multiple updates could trigger rebuilds
+            if needs_rebuild {
+                state_guard.last_menu_rebuild = Some(Instant::now());
+            }
+
+            effects.rebuild_menu = needs_rebuild;
+            // Update icon if active zone's state changed (e.g., to stopped)
+            effects.update_icon = needs_icon_update || switched;
+        }
+        SidecarMessage::Status { state: status_str, message } => {
+            log::info!("Source status: {} - {:?}", status_str, message);
+
+            // Update connection status
+            let status = match status_str.as_str() {
+                "discovering" => ConnectionStatus::Discovering,
+                "connected" => ConnectionStatus::Connected,
+                "disconnected" => ConnectionStatus::Disconnected,
+                "not_authorized" => ConnectionStatus::Error(
+                    "Not authorized. Please enable extension in Roon.".to_string(),
+                ),
+                _ => ConnectionStatus::Error(format!("Unknown status: {}", status_str)),
+            };
+
+            state.write().connection_status = status;
+
+            // Rebuild menu to show status
+            effects.rebuild_menu = true;
+        }
+        SidecarMessage::Error { message } => {
+            log::error!("Source error: {}", message);
+
+            state.write().connection_status = ConnectionStatus::Error(message);
+
+            // Rebuild menu to show error
+            effects.rebuild_menu = true;
+        }
+        SidecarMessage::SeekChanged { zone_id, seek_position } => {
+            let mut state_guard = state.write();
+
+            if let Some(track) = state_guard.all_zones.iter_mut()
+                .find(|z| z.zone_id == zone_id)
+                .and_then(|z| z.now_playing.as_mut())
+            {
+                track.set_position(seek_position);
+            }
+
+            if state_guard.active_zone_id.as_deref() == Some(zone_id.as_str()) {
+                if let Some(track) = state_guard.current_track.as_mut() {
+                    track.set_position(seek_position);
+                }
+            }
+        }
+        SidecarMessage::Artwork { image_key, data } => {
+            artwork.insert_data_url(&image_key, &data)
+                .with_context(|| format!("Failed to cache artwork {}", image_key))?;
+            log::debug!("Cached artwork {}", image_key);
+
+            // Redraw if the displayed track was waiting for this image
+            effects.update_icon = state.read().current_track.as_ref()
+                .and_then(|track| track.image_key.as_deref())
+                == Some(image_key.as_str());
+        }
+        SidecarMessage::Ack { .. } | SidecarMessage::CommandError { .. } => {
+            // Command replies are resolved by the source that sent the command
+        }
+        SidecarMessage::Unknown => {
+            // Newer source - skip what we don't understand and keep going
+            let skipped = record_skipped_message(state);
+            log::debug!("Skipped unknown source message ({} skipped so far)", skipped);
+        }
+    }
+
+    Ok(effects)
+}
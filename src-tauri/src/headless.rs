@@ -0,0 +1,198 @@
+//! Headless rendering without a tray
+//!
+//! `macaroon --headless <dir>` runs the configured source through the same
+//! state transitions as the tray app, but writes every rendered icon to a
+//! numbered PNG in `<dir>` instead of showing it. `--headless -` writes the
+//! PNGs back to back on stdout. Tauri is never started, so this works on a
+//! Linux box without a display; pair it with `MACAROON_SOURCE=replay`.
+//!
+//! Ticks run at the tray ticker's cadence in the source's time, so a replay
+//! at 4x speed ticks four times a second and its positions and smart
+//! switching grace periods run four times as fast.
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::artwork::ArtworkCache;
+use crate::compositor::Compositor;
+use crate::handler::{self, Effects};
+use crate::settings::Settings;
+use crate::source::{self, SharedSource};
+use crate::state::{self, SharedState};
+use crate::ticker::{self, PROGRESS_TICK};
+use crate::types::AppState;
+
+/// Where rendered icons go
+pub enum Output {
+    /// Numbered `icon-00001.png` files in a directory
+    Directory(PathBuf),
+    /// Concatenated PNGs on stdout
+    Stdout,
+}
+
+impl Output {
+    /// Parse `--headless <dir|->` from the command line
+    /// Returns None when the flag is absent so the tray app starts as usual
+    pub fn from_args() -> Result<Option<Self>> {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg != "--headless" {
+                continue;
+            }
+            return match args.next() {
+                Some(target) if target == "-" => Ok(Some(Self::Stdout)),
+                Some(target) => Ok(Some(Self::Directory(PathBuf::from(target)))),
+                None => bail!("--headless needs an output directory, or - for stdout"),
+            };
+        }
+        Ok(None)
+    }
+}
+
+/// Writes rendered icons to the output
+struct FrameWriter {
+    output: Output,
+    frames: u64,
+}
+
+impl FrameWriter {
+    fn new(output: Output) -> Result<Self> {
+        if let Output::Directory(dir) = &output {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create output directory {:?}", dir))?;
+        }
+        Ok(Self { output, frames: 0 })
+    }
+
+    fn write(&mut self, png: &[u8]) -> Result<()> {
+        self.frames += 1;
+        match &self.output {
+            Output::Directory(dir) => {
+                let path = dir.join(format!("icon-{:05}.png", self.frames));
+                std::fs::write(&path, png).with_context(|| format!("Failed to write {:?}", path))?;
+                log::debug!("Wrote {:?}", path);
+            }
+            Output::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(png).context("Failed to write icon to stdout")?;
+                stdout.flush().context("Failed to flush stdout")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run the source until it stops sending events, rendering after each change
+pub fn run(output: Output) -> Result<()> {
+    let compositor = Compositor::new();
+    let mut writer = FrameWriter::new(output)?;
+    let state = state::create_state();
+    let (settings, problems) = Settings::load();
+    settings.apply_to(&mut state.write(), problems);
+    let artwork = ArtworkCache::new();
+
+    // No resource dir - only the dev sidecar, MPD, MPRIS and replay work headless
+    let source = source::from_env(None);
+    let (events_tx, events_rx) = mpsc::channel();
+    source.start(events_tx)
+        .with_context(|| format!("Failed to start source {}", source.name()))?;
+    log::info!("Rendering {} headless", source.name());
+
+    let source_for_signal = source.clone();
+    ctrlc::set_handler(move || {
+        log::info!("Received interrupt signal, stopping source...");
+        if let Err(e) = source_for_signal.stop() {
+            log::error!("Error stopping source on interrupt: {}", e);
+        }
+        std::process::exit(0);
+    })
+    .context("Failed to set Ctrl+C handler")?;
+
+    // Initial placeholder, as the tray shows at startup
+    writer.write(&compositor.render_state(&state.read(), &artwork)?)?;
+
+    let speed = source.speed();
+    let tick = PROGRESS_TICK.div_f64(speed);
+    let mut next_tick = Instant::now() + tick;
+
+    // Ends when the source drops its sender, e.g. at the end of a replay
+    loop {
+        let effects = match events_rx.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+            Ok(event) => match handler::apply_event(event, &state, &artwork) {
+                Ok(effects) => effects,
+                Err(e) => {
+                    log::error!("Error handling {} event: {}", source.name(), e);
+                    continue;
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => {
+                next_tick += tick;
+                // The wall clock only covered `tick` of the PROGRESS_TICK the source moved on
+                shift_clocks(&mut state.write(), (PROGRESS_TICK - tick).as_secs_f64());
+                let mut effects = handler::apply_tick(&state);
+                effects.update_icon |= ticker::needs_redraw(&state);
+                effects
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        if let Err(e) = apply_effects(effects, &source, &state, &compositor, &artwork, &mut writer) {
+            log::error!("Failed to render icon: {}", e);
+        }
+    }
+
+    source.stop()?;
+    log::info!("Headless run finished after {} icons", writer.frames);
+    Ok(())
+}
+
+/// Move every timestamp in the state `seconds` into the past (or the future
+/// when negative), as if that much more time had gone by
+fn shift_clocks(state: &mut AppState, seconds: f64) {
+    if seconds == 0.0 {
+        return;
+    }
+    let by = Duration::from_secs_f64(seconds.abs());
+    let shift = |instant: &mut Instant| {
+        let shifted = if seconds > 0.0 { instant.checked_sub(by) } else { instant.checked_add(by) };
+        if let Some(shifted) = shifted {
+            *instant = shifted;
+        }
+    };
+
+    if let Some(track) = state.current_track.as_mut() {
+        shift(&mut track.position_updated_at);
+    }
+    for zone in &mut state.all_zones {
+        shift(&mut zone.state_changed_at);
+        if let Some(now_playing) = zone.now_playing.as_mut() {
+            shift(&mut now_playing.position_updated_at);
+        }
+    }
+    if let Some(stopped_at) = state.preferred_zone_stopped_at.as_mut() {
+        shift(stopped_at);
+    }
+}
+
+fn apply_effects(
+    effects: Effects,
+    source: &SharedSource,
+    state: &SharedState,
+    compositor: &Compositor,
+    artwork: &ArtworkCache,
+    writer: &mut FrameWriter,
+) -> Result<()> {
+    if let Some(image_key) = effects.fetch_artwork {
+        handler::request_artwork(source, image_key);
+    }
+
+    // There is no menu to rebuild; only the icon is rendered
+    if effects.update_icon {
+        writer.write(&compositor.render_state(&state.read(), artwork)?)?;
+    }
+
+    Ok(())
+}
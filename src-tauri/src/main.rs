@@ -1,20 +1,53 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod artwork;
 mod autostart;
 mod compositor;
-mod sidecar;
+mod credits;
+mod fonts;
+mod handler;
+mod headless;
+mod lru;
+mod paths;
+mod quantize;
+mod settings;
+mod shaping;
+mod source;
 mod state;
+mod template;
+mod theme;
+mod ticker;
 mod tray;
+mod truncate;
 mod types;
 
+use std::sync::{mpsc, Arc};
 use tauri::Manager;
 
+use source::SharedSource;
+
 fn main() {
     // Initialize logger
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
         .init();
 
+    // Headless mode renders icons to PNGs without starting Tauri
+    match headless::Output::from_args() {
+        Ok(Some(output)) => {
+            if let Err(e) = headless::run(output) {
+                log::error!("Headless run failed: {:#}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("{:#}", e);
+            std::process::exit(2);
+        }
+    }
+
     log::info!("Starting Macaroon menu bar app");
 
     // Hide from Dock and Cmd+Tab (macOS only)
@@ -44,8 +77,14 @@ fn main() {
 fn setup_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Setting up application");
 
-            // Create shared state
+            // Create shared state, restoring the zone preference and display settings
             let state = state::create_state();
+            let (loaded_settings, settings_problems) = settings::Settings::load();
+            loaded_settings.apply_to(&mut state.write(), settings_problems);
+
+            // Artwork cache must exist before the source starts sending images
+            let artwork = Arc::new(artwork::ArtworkCache::new());
+            app.manage(artwork.clone());
 
             // Initialize system tray first
             tray::TrayManager::setup(app.handle(), state.clone())
@@ -53,39 +92,73 @@ fn setup_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
 
             log::info!("System tray initialized");
 
-            // Spawn sidecar process
+            // Redraw the icon periodically while playing (progress bar)
+            ticker::spawn(app.handle().clone(), state.clone());
+            ticker::spawn_animation(app.handle().clone(), state.clone());
+
+            // Re-apply settings.json when it is edited while running
+            let app_handle = app.handle().clone();
+            let state_for_settings = state.clone();
+            settings::spawn_watcher(state.clone(), move |effects| {
+                let app_clone = app_handle.clone();
+                let state_clone = state_for_settings.clone();
+                if let Err(e) = app_handle.run_on_main_thread(move || {
+                    if effects.rebuild_menu {
+                        if let Err(e) = tray::TrayManager::rebuild_menu(&app_clone, &state_clone) {
+                            log::error!("Failed to rebuild menu after settings change: {}", e);
+                        }
+                    }
+                    if effects.update_icon {
+                        if let Err(e) = tray::TrayManager::update_icon(&app_clone, &state_clone) {
+                            log::error!("Failed to update icon after settings change: {}", e);
+                        }
+                    }
+                }) {
+                    log::error!("Failed to dispatch settings change to main thread: {}", e);
+                }
+            });
+
+            // Start the now-playing source (the Roon sidecar unless MACAROON_SOURCE says otherwise)
             // Zones will arrive and populate the menu within ~500ms
-            let sidecar_manager = sidecar::SidecarManager::new();
-            match sidecar_manager.spawn(app.handle(), state.clone()) {
+            let source = source::from_env(app.path().resource_dir().ok());
+            let (events_tx, events_rx) = mpsc::channel();
+            handler::spawn_dispatcher(
+                app.handle().clone(),
+                state.clone(),
+                artwork,
+                source.clone(),
+                events_rx,
+            );
+            match source.start(events_tx) {
                 Ok(_) => {
-                    log::info!("Sidecar spawned successfully");
+                    log::info!("Source {} started successfully", source.name());
                 }
                 Err(e) => {
-                    log::error!("Failed to spawn sidecar: {}", e);
-                    // Continue running even if sidecar fails
+                    log::error!("Failed to start source {}: {}", source.name(), e);
+                    // Continue running even if the source fails
                 }
             }
 
             // Setup signal handler for Ctrl+C (SIGINT) and SIGTERM
-            let sidecar_for_signal = sidecar_manager.clone();
+            let source_for_signal = source.clone();
             ctrlc::set_handler(move || {
-                log::info!("Received interrupt signal (Ctrl+C), cleaning up sidecar...");
-                // Stop sidecar and wait for it to complete before exiting
-                match sidecar_for_signal.stop() {
+                log::info!("Received interrupt signal (Ctrl+C), cleaning up source...");
+                // Stop source and wait for it to complete before exiting
+                match source_for_signal.stop() {
                     Ok(_) => {
-                        log::info!("Sidecar stopped successfully on interrupt");
+                        log::info!("Source stopped successfully on interrupt");
                     }
                     Err(e) => {
-                        log::error!("Error stopping sidecar on interrupt: {}", e);
+                        log::error!("Error stopping source on interrupt: {}", e);
                     }
                 }
-                // Only exit after sidecar has been stopped
+                // Only exit after source has been stopped
                 std::process::exit(0);
             })
             .expect("Failed to set Ctrl+C handler");
 
-    // Store sidecar manager in app state for cleanup
-    app.manage(sidecar_manager);
+    // Store source in app state for commands and cleanup
+    app.manage(source);
 
     // Detect dark mode once at startup and store it
     {
@@ -101,22 +174,22 @@ fn setup_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
 fn run_handler(app_handle: &tauri::AppHandle, event: tauri::RunEvent) {
     match event {
         tauri::RunEvent::Exit => {
-            log::info!("App exit event received, cleaning up sidecar...");
+            log::info!("App exit event received, cleaning up source...");
 
-            // Get the sidecar manager from managed state and stop it
-            if let Some(sidecar) = app_handle.try_state::<sidecar::SidecarManager>() {
-                if let Err(e) = sidecar.stop() {
-                    log::error!("Error stopping sidecar on exit: {}", e);
+            // Get the source from managed state and stop it
+            if let Some(source) = app_handle.try_state::<SharedSource>() {
+                if let Err(e) = source.stop() {
+                    log::error!("Error stopping source on exit: {}", e);
                 }
             }
         }
         tauri::RunEvent::ExitRequested { .. } => {
-            log::info!("App exit requested, cleaning up sidecar...");
+            log::info!("App exit requested, cleaning up source...");
 
-            // Get the sidecar manager from managed state and stop it
-            if let Some(sidecar) = app_handle.try_state::<sidecar::SidecarManager>() {
-                if let Err(e) = sidecar.stop() {
-                    log::error!("Error stopping sidecar on exit request: {}", e);
+            // Get the source from managed state and stop it
+            if let Some(source) = app_handle.try_state::<SharedSource>() {
+                if let Err(e) = source.stop() {
+                    log::error!("Error stopping source on exit request: {}", e);
                 }
             }
         }
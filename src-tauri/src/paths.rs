@@ -0,0 +1,53 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the settings file inside the config directory
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Get the app's config directory - the same one the sidecar stores pairing data in
+/// Uses ~/Library/Application Support/Macaroon on macOS and ~/.config/macaroon elsewhere
+pub fn config_dir() -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library").join("Application Support").join("Macaroon"))
+    } else {
+        Some(home.join(".config").join("macaroon"))
+    }
+}
+
+/// Directory for cached album artwork
+pub fn artwork_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("artwork"))
+}
+
+/// Path of the persisted settings file
+pub fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(SETTINGS_FILE))
+}
+
+/// Create a directory (and parents) readable only by the current user
+pub fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    builder.create(dir)
+}
+
+/// Write a file by writing a temporary sibling and renaming it into place,
+/// so readers never see a partially written file
+/// The data is synced before the rename so a crash can't leave an empty file behind
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,107 @@
+//! Colour palette extraction from artwork
+//!
+//! Median cut: the artwork's pixels are split into boxes along their widest
+//! colour channel until there are enough boxes, and each box's average is a
+//! swatch. Swatches are ranked by how much of the artwork they cover,
+//! favouring colourful ones so greys and near-blacks rarely win.
+
+use image::RgbaImage;
+
+use crate::theme::{contrast_ratio, Color, MIN_CONTRAST};
+
+/// Pixels more transparent than this don't count
+const MIN_ALPHA: u8 = 128;
+
+/// A colour from the artwork and how many pixels it stands for
+#[derive(Debug, Clone, Copy)]
+pub struct Swatch {
+    pub color: Color,
+    pub population: u32,
+}
+
+impl Swatch {
+    /// Coverage weighted by saturation
+    fn score(&self) -> f32 {
+        let [r, g, b, _] = self.color.0;
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        let saturation = if max == 0 { 0.0 } else { f32::from(max - min) / f32::from(max) };
+        self.population as f32 * (0.25 + saturation)
+    }
+}
+
+/// Reduce the image to at most `max_colors` swatches, most prominent first
+pub fn swatches(image: &RgbaImage, max_colors: usize) -> Vec<Swatch> {
+    let pixels: Vec<[u8; 3]> = image.pixels()
+        .filter(|pixel| pixel[3] >= MIN_ALPHA)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    if pixels.is_empty() {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < max_colors {
+        // Split the box with the widest spread in any channel
+        let Some((index, channel, _)) = boxes.iter()
+            .enumerate()
+            .map(|(index, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (index, channel, range)
+            })
+            .filter(|&(_, _, range)| range > 0)
+            .max_by_key(|&(_, _, range)| range)
+        else {
+            break;
+        };
+
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    let mut swatches: Vec<Swatch> = boxes.iter().map(|pixels| average(pixels)).collect();
+    swatches.sort_by(|a, b| b.score().total_cmp(&a.score()));
+    swatches
+}
+
+/// The most prominent swatch, whatever it looks like
+pub fn dominant(swatches: &[Swatch]) -> Option<Color> {
+    swatches.first().map(|swatch| swatch.color)
+}
+
+/// The most prominent swatch that stays readable on `background`
+pub fn accent(swatches: &[Swatch], background: Color) -> Option<Color> {
+    swatches.iter()
+        .map(|swatch| swatch.color)
+        .find(|&color| contrast_ratio(color, background) >= MIN_CONTRAST)
+}
+
+/// The channel with the largest spread of values, and that spread
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+                (min.min(pixel[channel]), max.max(pixel[channel]))
+            });
+            (channel, max.saturating_sub(min))
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+fn average(pixels: &[[u8; 3]]) -> Swatch {
+    let mut sums = [0u64; 3];
+    for pixel in pixels {
+        for (sum, value) in sums.iter_mut().zip(pixel) {
+            *sum += u64::from(*value);
+        }
+    }
+    let count = pixels.len().max(1) as u64;
+    let [r, g, b] = sums.map(|sum| (sum / count) as u8);
+    Swatch {
+        color: Color([r, g, b, 255]),
+        population: pixels.len() as u32,
+    }
+}
@@ -0,0 +1,582 @@
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::fonts;
+use crate::handler::{self, Effects};
+use crate::paths;
+use crate::state::SharedState;
+use crate::template::Template;
+use crate::types::{AppState, BehaviorSettings, DisplaySettings, ZonePreference};
+
+/// Settings file format written by this version
+/// Files without a version (0) predate versioning and share version 1's layout
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// How often the settings file is checked for edits
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Allowed ranges for numeric settings
+const FONT_SIZE_RANGE: (f32, f32) = (8.0, 22.0);
+const MAX_WIDTH_RANGE: (u32, u32) = (22, 2000);
+const GRACE_PERIOD_RANGE: (u32, u32) = (0, 24 * 60);
+
+/// User choices that survive restarts, stored as settings.json in the config dir
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Settings {
+    pub version: u32,
+    pub zone_preference: ZonePreference,
+    pub display: DisplaySettings,
+    pub behavior: BehaviorSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            zone_preference: ZonePreference::default(),
+            display: DisplaySettings::default(),
+            behavior: BehaviorSettings::default(),
+        }
+    }
+}
+
+/// Read one value from a settings object, recording a problem if it has the wrong type
+fn read_field<T: DeserializeOwned>(
+    object: &Map<String, Value>,
+    key: &str,
+    path: &str,
+    problems: &mut Vec<String>,
+) -> Option<T> {
+    let value = object.get(key)?;
+    match T::deserialize(value) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            problems.push(format!("{}: {}", path, e));
+            None
+        }
+    }
+}
+
+/// Read a nested settings object such as "display"
+fn read_section<'a>(
+    root: &'a Map<String, Value>,
+    key: &str,
+    problems: &mut Vec<String>,
+) -> Option<&'a Map<String, Value>> {
+    match root.get(key)? {
+        Value::Object(section) => Some(section),
+        _ => {
+            problems.push(format!("{}: expected an object", key));
+            None
+        }
+    }
+}
+
+impl Settings {
+    /// Parse a settings file, replacing invalid values with defaults
+    /// Returns the settings and a description of each value that was replaced;
+    /// only text that isn't a JSON object at all is an error
+    pub fn parse(text: &str) -> Result<(Self, Vec<String>)> {
+        let root = match serde_json::from_str(text).context("Invalid JSON")? {
+            Value::Object(root) => root,
+            _ => bail!("Expected a JSON object"),
+        };
+
+        let mut problems = Vec::new();
+        let mut settings = Self { version: 0, ..Self::default() };
+
+        if let Some(version) = read_field(&root, "version", "version", &mut problems) {
+            settings.version = version;
+        }
+        if settings.version > SETTINGS_VERSION {
+            problems.push(format!(
+                "version {} is newer than this app understands ({})",
+                settings.version, SETTINGS_VERSION
+            ));
+        }
+
+        if let Some(preference) = read_field(&root, "zone_preference", "zone_preference", &mut problems) {
+            settings.zone_preference = preference;
+        }
+
+        if let Some(display) = read_section(&root, "display", &mut problems) {
+            let current = &mut settings.display;
+            if let Some(value) = read_field(display, "progress_bar", "display.progress_bar", &mut problems) {
+                current.progress_bar = value;
+            }
+            if let Some(value) = read_field(display, "font_size", "display.font_size", &mut problems) {
+                current.font_size = value;
+            }
+            if let Some(value) = read_field(display, "max_width", "display.max_width", &mut problems) {
+                current.max_width = value;
+            }
+            if let Some(value) = read_field(display, "layout", "display.layout", &mut problems) {
+                current.layout = value;
+            }
+            if let Some(value) = read_field(display, "overflow", "display.overflow", &mut problems) {
+                current.overflow = value;
+            }
+            if let Some(value) = read_field(display, "paused", "display.paused", &mut problems) {
+                current.paused = value;
+            }
+            if let Some(value) = read_field(display, "truncation", "display.truncation", &mut problems) {
+                current.truncation = value;
+            }
+            if let Some(value) = read_field(display, "template", "display.template", &mut problems) {
+                current.template = value;
+            }
+            if let Some(value) = read_field(display, "credits", "display.credits", &mut problems) {
+                current.credits = value;
+            }
+            if let Some(value) = read_field(display, "fonts", "display.fonts", &mut problems) {
+                current.fonts = value;
+            }
+            if let Some(value) = read_field(display, "theme", "display.theme", &mut problems) {
+                current.theme = value;
+            }
+            if let Some(value) = read_field(display, "artwork_color", "display.artwork_color", &mut problems) {
+                current.artwork_color = value;
+            }
+        }
+
+        if let Some(behavior) = read_section(&root, "behavior", &mut problems) {
+            let current = &mut settings.behavior;
+            if let Some(value) = read_field(behavior, "smart_switching", "behavior.smart_switching", &mut problems) {
+                current.smart_switching = value;
+            }
+            if let Some(value) = read_field(behavior, "grace_period_mins", "behavior.grace_period_mins", &mut problems) {
+                current.grace_period_mins = value;
+            }
+        }
+
+        settings.validate(&mut problems);
+        Ok((settings, problems))
+    }
+
+    /// Reset out-of-range values to their defaults
+    fn validate(&mut self, problems: &mut Vec<String>) {
+        let display_defaults = DisplaySettings::default();
+        let behavior_defaults = BehaviorSettings::default();
+
+        let (min, max) = FONT_SIZE_RANGE;
+        if !(min..=max).contains(&self.display.font_size) {
+            problems.push(format!("display.font_size must be between {} and {}", min, max));
+            self.display.font_size = display_defaults.font_size;
+        }
+
+        let (min, max) = MAX_WIDTH_RANGE;
+        if !(min..=max).contains(&self.display.max_width) {
+            problems.push(format!("display.max_width must be between {} and {}", min, max));
+            self.display.max_width = display_defaults.max_width;
+        }
+
+        if let Err(e) = Template::parse(&self.display.template) {
+            problems.push(format!("display.template: {}", e));
+            self.display.template = display_defaults.template.clone();
+        }
+
+        self.display.fonts.retain(|font| {
+            let found = fonts::resolve(font).is_some();
+            if !found {
+                problems.push(format!("display.fonts: can't find font {:?}", font));
+            }
+            found
+        });
+
+        for problem in self.display.theme.drop_unreadable() {
+            problems.push(format!("display.theme.{}", problem));
+        }
+
+        let (min, max) = GRACE_PERIOD_RANGE;
+        if !(min..=max).contains(&self.behavior.grace_period_mins) {
+            problems.push(format!("behavior.grace_period_mins must be between {} and {}", min, max));
+            self.behavior.grace_period_mins = behavior_defaults.grace_period_mins;
+        }
+    }
+
+    /// Load the settings file, falling back to defaults if it is missing or unreadable
+    /// A corrupt file is moved aside to settings.json.corrupt so it isn't overwritten
+    /// Also returns the invalid values that were replaced with defaults
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(path) = paths::settings_path() else {
+            log::warn!("No config directory (HOME unset), using default settings");
+            return (Self::default(), Vec::new());
+        };
+
+        match Self::read(&path) {
+            Ok(Some((settings, problems))) => {
+                log::info!("Loaded settings from {:?}", path);
+                for problem in &problems {
+                    log::warn!("Invalid setting {}", problem);
+                }
+                (settings, problems)
+            }
+            Ok(None) => (Self::default(), Vec::new()),
+            Err(e) => {
+                log::warn!("Ignoring unreadable settings file: {:#}", e);
+                let backup = path.with_extension("json.corrupt");
+                let problem = match std::fs::rename(&path, &backup) {
+                    Ok(_) => {
+                        log::warn!("Moved corrupt settings to {:?}", backup);
+                        "settings.json was unreadable, moved to settings.json.corrupt".to_string()
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to move corrupt settings aside: {}", e);
+                        "settings.json is unreadable, using defaults".to_string()
+                    }
+                };
+                (Self::default(), vec![problem])
+            }
+        }
+    }
+
+    /// Read and parse the file, returning None if it doesn't exist yet
+    fn read(path: &Path) -> Result<Option<(Self, Vec<String>)>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+
+        let parsed = Self::parse(&text).with_context(|| format!("Failed to parse {:?}", path))?;
+        Ok(Some(parsed))
+    }
+
+    /// Write the settings file atomically
+    /// Only values that differ from what the file holds are written, so keys
+    /// this version doesn't know and invalid values the user hasn't fixed yet
+    /// are kept
+    pub fn save(&self) -> Result<()> {
+        let dir = paths::config_dir().context("No config directory (HOME unset)")?;
+        paths::create_private_dir(&dir)
+            .with_context(|| format!("Failed to create config directory {:?}", dir))?;
+
+        let path = dir.join(paths::SETTINGS_FILE);
+        let existing = std::fs::read_to_string(&path).ok();
+        let merged = self.merge_into(existing.as_deref())?;
+        let json = serde_json::to_vec_pretty(&merged).context("Failed to serialize settings")?;
+        paths::write_atomic(&path, &json).with_context(|| format!("Failed to write {:?}", path))?;
+
+        log::debug!("Saved settings to {:?}", path);
+        Ok(())
+    }
+
+    /// These settings written over the text of an existing settings file
+    /// Falls back to just these settings when the file is missing or unreadable
+    fn merge_into(&self, existing: Option<&str>) -> Result<Value> {
+        let after = serde_json::to_value(self).context("Failed to serialize settings")?;
+
+        let Some(text) = existing else {
+            return Ok(after);
+        };
+        let (Ok(mut root @ Value::Object(_)), Ok((before, _))) = (serde_json::from_str::<Value>(text), Self::parse(text)) else {
+            return Ok(after);
+        };
+
+        let before = serde_json::to_value(before).context("Failed to serialize settings")?;
+        merge_changes(&mut root, &before, &after);
+        Ok(root)
+    }
+
+    /// Capture the persisted parts of the app state
+    pub fn from_state(state: &AppState) -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            zone_preference: state.zone_preference.clone(),
+            display: state.display_settings.clone(),
+            behavior: state.behavior_settings.clone(),
+        }
+    }
+
+    /// Restore the persisted parts of the app state at startup
+    pub fn apply_to(self, state: &mut AppState, problems: Vec<String>) {
+        state.zone_preference = self.selected_preference();
+        state.display_settings = self.display;
+        state.behavior_settings = self.behavior;
+        state.settings_problems = problems;
+    }
+
+    /// The zone preference with the behaviour settings applied to a selected zone
+    fn selected_preference(&self) -> ZonePreference {
+        match &self.zone_preference {
+            ZonePreference::Selected { zone_id, .. } => ZonePreference::Selected {
+                zone_id: zone_id.clone(),
+                smart_switching: self.behavior.smart_switching,
+                grace_period_mins: self.behavior.grace_period_mins,
+            },
+            ZonePreference::Auto => ZonePreference::Auto,
+        }
+    }
+}
+
+/// Copy the values that differ between `before` and `after` into `file`,
+/// leaving everything else in `file` as it was
+fn merge_changes(file: &mut Value, before: &Value, after: &Value) {
+    if before == after {
+        return;
+    }
+
+    match (file, before, after) {
+        (Value::Object(file), Value::Object(before), Value::Object(after)) => {
+            for (key, value) in after {
+                match (file.get_mut(key), before.get(key)) {
+                    (Some(file_value), Some(before_value)) => merge_changes(file_value, before_value, value),
+                    _ => {
+                        file.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            for key in before.keys().filter(|key| !after.contains_key(*key)) {
+                file.remove(key);
+            }
+        }
+        (file, _, after) => *file = after.clone(),
+    }
+}
+
+/// Save the current state's settings, logging failures
+/// Losing a preference isn't worth interrupting the user over
+pub fn save_state(state: &SharedState) {
+    // Snapshot first so the state lock isn't held during file I/O
+    let settings = Settings::from_state(&state.read());
+    if let Err(e) = settings.save() {
+        log::error!("Failed to save settings: {:#}", e);
+    }
+}
+
+/// Re-read the settings file and apply what changed to the running app
+/// A file that can't be parsed is reported in the menu rather than moved aside,
+/// since it is probably being edited
+fn reload(path: &Path, state: &SharedState) -> Effects {
+    let (settings, problems) = match Settings::read(path) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => return Effects::default(),
+        Err(e) => {
+            log::warn!("Ignoring settings change: {:#}", e);
+            let problems = vec![format!("settings.json: {:#}", e)];
+            let mut state_guard = state.write();
+            let changed = state_guard.settings_problems != problems;
+            state_guard.settings_problems = problems;
+            return Effects { rebuild_menu: changed, ..Effects::default() };
+        }
+    };
+
+    let mut state_guard = state.write();
+    let mut effects = Effects::default();
+
+    if state_guard.settings_problems != problems {
+        for problem in &problems {
+            log::warn!("Invalid setting {}", problem);
+        }
+        state_guard.settings_problems = problems;
+        effects.rebuild_menu = true;
+    }
+
+    let preference = settings.selected_preference();
+    if state_guard.zone_preference != preference {
+        let same_zone = match (&state_guard.zone_preference, &preference) {
+            (ZonePreference::Selected { zone_id: old, .. }, ZonePreference::Selected { zone_id: new, .. }) => old == new,
+            _ => false,
+        };
+        if same_zone {
+            log::info!("Smart switching changed in settings file");
+            effects.update_icon = handler::update_smart_switching(&mut state_guard, preference);
+        } else {
+            log::info!("Zone preference changed in settings file");
+            handler::select_zone(&mut state_guard, preference);
+            effects.update_icon = true;
+        }
+        effects.rebuild_menu = true;
+    }
+
+    if state_guard.display_settings != settings.display {
+        log::info!("Display settings changed in settings file");
+        state_guard.display_settings = settings.display;
+        effects.rebuild_menu = true;
+        effects.update_icon = true;
+    }
+
+    if state_guard.behavior_settings != settings.behavior {
+        log::info!("Behavior settings changed in settings file");
+        state_guard.behavior_settings = settings.behavior;
+    }
+
+    effects
+}
+
+/// Modification stamp used to notice edits
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Spawn a thread that re-applies the settings file whenever it changes
+/// `on_change` is called with the UI work needed after each reload
+pub fn spawn_watcher<F>(state: SharedState, on_change: F)
+where
+    F: Fn(Effects) + Send + 'static,
+{
+    let Some(path) = paths::settings_path() else {
+        return;
+    };
+
+    let result = thread::Builder::new()
+        .name("settings-watcher".to_string())
+        .spawn(move || {
+            let mut last_stamp = file_stamp(&path);
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+
+                let stamp = file_stamp(&path);
+                if stamp == last_stamp {
+                    continue;
+                }
+                last_stamp = stamp;
+
+                let effects = reload(&path, &state);
+                if effects.rebuild_menu || effects.update_icon {
+                    on_change(effects);
+                }
+            }
+        });
+
+    if let Err(e) = result {
+        log::error!("Failed to spawn settings watcher thread: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state;
+    use crate::types::{PlaybackState, ProgressBarStyle, Zone};
+    use std::time::Instant;
+
+    fn merged(settings: &Settings, existing: &str) -> Value {
+        settings.merge_into(Some(existing)).unwrap()
+    }
+
+    #[test]
+    fn saving_keeps_unknown_keys_and_invalid_values() {
+        let existing = r#"{
+            "version": 1,
+            "added_later": {"keep": true},
+            "display": {"font_size": 99, "progress_bar": "off", "sparkles": 3},
+            "behavior": {"grace_period_mins": "ten"}
+        }"#;
+        let (mut settings, problems) = Settings::parse(existing).unwrap();
+        assert_eq!(problems.len(), 2);
+
+        settings.display.progress_bar = ProgressBarStyle::UnderText;
+        let root = merged(&settings, existing);
+
+        assert_eq!(root["display"]["progress_bar"], "under_text");
+        assert_eq!(root["added_later"], serde_json::json!({"keep": true}));
+        assert_eq!(root["display"]["sparkles"], 3);
+        assert_eq!(root["display"]["font_size"], 99);
+        assert_eq!(root["behavior"]["grace_period_mins"], "ten");
+    }
+
+    #[test]
+    fn saving_writes_what_changed() {
+        let existing = r#"{"display": {"font_size": 99}, "behavior": {"grace_period_mins": "ten"}}"#;
+        let (mut settings, _) = Settings::parse(existing).unwrap();
+        settings.version = SETTINGS_VERSION;
+
+        // Choosing a value in the app replaces the invalid one
+        settings.display.font_size = 15.0;
+        settings.behavior.grace_period_mins = 20;
+        settings.zone_preference = ZonePreference::Selected {
+            zone_id: "z1".to_string(),
+            smart_switching: true,
+            grace_period_mins: 20,
+        };
+        let root = merged(&settings, existing);
+
+        assert_eq!(root["version"], SETTINGS_VERSION);
+        assert_eq!(root["display"]["font_size"], 15.0);
+        assert_eq!(root["behavior"]["grace_period_mins"], 20);
+        assert_eq!(root["zone_preference"]["mode"], "selected");
+        assert_eq!(root["zone_preference"]["zone_id"], "z1");
+        assert_eq!(Settings::parse(&root.to_string()).unwrap(), (settings.clone(), Vec::new()));
+
+        // Going back to automatic drops the selected zone's fields
+        let text = root.to_string();
+        settings.zone_preference = ZonePreference::Auto;
+        let root = merged(&settings, &text);
+        assert_eq!(root["zone_preference"], serde_json::json!({"mode": "auto"}));
+    }
+
+    #[test]
+    fn saving_over_a_missing_or_broken_file_writes_everything() {
+        let settings = Settings::default();
+        let expected = serde_json::to_value(&settings).unwrap();
+        assert_eq!(settings.merge_into(None).unwrap(), expected);
+        assert_eq!(merged(&settings, "{not json"), expected);
+        assert_eq!(merged(&settings, "[1, 2]"), expected);
+    }
+
+    fn zone(zone_id: &str, state: PlaybackState) -> Zone {
+        Zone {
+            zone_id: zone_id.to_string(),
+            display_name: zone_id.to_string(),
+            state,
+            now_playing: None,
+            state_changed_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn behaviour_edits_keep_following_a_zone() {
+        let path = std::env::temp_dir().join(format!("macaroon-settings-{}.json", std::process::id()));
+        let state = state::create_state();
+        let stopped_at = Instant::now() - Duration::from_secs(20 * 60);
+        {
+            let mut state_guard = state.write();
+            state_guard.all_zones = vec![zone("z1", PlaybackState::Stopped), zone("z2", PlaybackState::Playing)];
+            state_guard.zone_preference = ZonePreference::Selected {
+                zone_id: "z1".to_string(),
+                smart_switching: true,
+                grace_period_mins: 5,
+            };
+            state_guard.behavior_settings = BehaviorSettings { smart_switching: true, grace_period_mins: 5 };
+            state_guard.preferred_zone_stopped_at = Some(stopped_at);
+            state_guard.is_smart_switched = true;
+            state_guard.active_zone_id = Some("z2".to_string());
+        }
+
+        let write = |behavior: &str| {
+            let text = format!(r#"{{"zone_preference": {{"mode": "selected", "zone_id": "z1"}}, "behavior": {}}}"#, behavior);
+            std::fs::write(&path, text).unwrap();
+        };
+
+        // A longer grace period that has still run out
+        write(r#"{"grace_period_mins": 10}"#);
+        let effects = reload(&path, &state);
+        {
+            let state_guard = state.read();
+            assert!(state_guard.is_smart_switched);
+            assert_eq!(state_guard.active_zone_id.as_deref(), Some("z2"));
+            assert_eq!(state_guard.preferred_zone_stopped_at, Some(stopped_at));
+            assert_eq!(state_guard.behavior_settings.grace_period_mins, 10);
+            assert!(!effects.update_icon);
+        }
+
+        // Turning smart switching off goes back to the selected zone
+        write(r#"{"smart_switching": false, "grace_period_mins": 10}"#);
+        let effects = reload(&path, &state);
+        {
+            let state_guard = state.read();
+            assert!(!state_guard.is_smart_switched);
+            assert_eq!(state_guard.active_zone_id.as_deref(), Some("z1"));
+            assert!(effects.update_icon);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
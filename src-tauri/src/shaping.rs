@@ -0,0 +1,182 @@
+//! Text shaping and bidirectional layout
+//!
+//! Text is split into bidi runs, each run into font runs by the font chain,
+//! and each font run is shaped with rustybuzz. Measuring and drawing both use
+//! the shaped glyphs, so kerning, ligatures, combining marks and right-to-left
+//! scripts come out the same width they are drawn.
+
+use ab_glyph::{point, Font, GlyphId, GlyphImageFormat, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use rustybuzz::{Direction, UnicodeBuffer};
+use unicode_bidi::BidiInfo;
+
+use std::sync::Arc;
+
+use crate::fonts::{FontChain, LoadedFont};
+
+/// A glyph placed on the line
+struct ShapedGlyph {
+    font: Arc<LoadedFont>,
+    id: GlyphId,
+    /// Pen position from the start of the line, including the shaper's offset
+    x: f32,
+    /// Shaper's vertical offset, positive up
+    y_offset: f32,
+}
+
+/// A line of text laid out in visual order
+pub struct ShapedText {
+    glyphs: Vec<ShapedGlyph>,
+    width: f32,
+    /// Primary font ascent, shared by every run so baselines line up
+    ascent: f32,
+    scale: PxScale,
+}
+
+/// Shape a single line of text with the chain's fonts
+pub fn shape(fonts: &FontChain, text: &str, scale: PxScale) -> ShapedText {
+    let mut shaped = ShapedText {
+        glyphs: Vec::new(),
+        width: 0.0,
+        ascent: fonts.primary().font.as_scaled(scale).ascent(),
+        scale,
+    };
+    if text.is_empty() {
+        return shaped;
+    }
+
+    let bidi = BidiInfo::new(text, None);
+    for paragraph in &bidi.paragraphs {
+        let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+        for run in runs {
+            let rtl = levels[run.start].is_rtl();
+            let mut font_runs = fonts.runs(&text[run.clone()]);
+            // Font runs come back in logical order
+            if rtl {
+                font_runs.reverse();
+            }
+            for font_run in font_runs {
+                let start = run.start + font_run.range.start;
+                let end = run.start + font_run.range.end;
+                shaped.push_run(&font_run.font, &text[start..end], start, rtl);
+            }
+        }
+    }
+
+    shaped
+}
+
+impl ShapedText {
+    /// Advance width of the whole line in pixels
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    /// Draw the line with its top-left corner at (x, y)
+    pub fn draw(&self, canvas: &mut RgbaImage, color: Rgba<u8>, x: i32, y: i32) {
+        let baseline = y as f32 + self.ascent;
+
+        for glyph in &self.glyphs {
+            let origin = point(x as f32 + glyph.x, baseline - glyph.y_offset);
+            let font = &glyph.font.font;
+
+            if let Some(outline) = font.outline_glyph(glyph.id.with_scale_and_position(self.scale, origin)) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i32 + gx as i32;
+                    let py = bounds.min.y as i32 + gy as i32;
+                    blend_pixel(canvas, px, py, color, coverage);
+                });
+            } else {
+                draw_image_glyph(canvas, font, glyph.id, self.scale, origin.x, origin.y);
+            }
+        }
+    }
+
+    /// Shape one font run and append its glyphs at the end of the line
+    fn push_run(&mut self, font: &Arc<LoadedFont>, text: &str, offset: usize, rtl: bool) {
+        let scaled = font.font.as_scaled(self.scale);
+        let (h_scale, v_scale) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+
+        let Some(face) = font.shaping_face() else {
+            // ab_glyph could parse it, so this shouldn't happen; lay it out unshaped
+            log::warn!("Font can't be shaped, laying out {:?} without shaping", text);
+            for ch in text.chars().filter(|ch| !ch.is_control()) {
+                let id = font.font.glyph_id(ch);
+                let advance = scaled.h_advance(id);
+                self.push_glyph(font, id, 0.0, 0.0, advance);
+            }
+            return;
+        };
+
+        let mut buffer = UnicodeBuffer::new();
+        for (index, ch) in text.char_indices().filter(|(_, ch)| !ch.is_control()) {
+            buffer.add(ch, (offset + index) as u32);
+        }
+        buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+        buffer.guess_segment_properties();
+
+        let output = rustybuzz::shape(&face, &[], buffer);
+        for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+            self.push_glyph(
+                font,
+                GlyphId(info.glyph_id as u16),
+                position.x_offset as f32 * h_scale,
+                position.y_offset as f32 * v_scale,
+                position.x_advance as f32 * h_scale,
+            );
+        }
+    }
+
+    fn push_glyph(&mut self, font: &Arc<LoadedFont>, id: GlyphId, x_offset: f32, y_offset: f32, advance: f32) {
+        self.glyphs.push(ShapedGlyph {
+            font: font.clone(),
+            id,
+            x: self.width + x_offset,
+            y_offset,
+        });
+        self.width += advance;
+    }
+}
+
+/// Mix `color` into a pixel by glyph coverage, ignoring pixels off the canvas
+fn blend_pixel(canvas: &mut RgbaImage, x: i32, y: i32, color: Rgba<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x as u32 >= canvas.width() || y as u32 >= canvas.height() {
+        return;
+    }
+    let coverage = coverage.clamp(0.0, 1.0);
+    let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+    for (channel, target) in pixel.0.iter_mut().zip(color.0) {
+        *channel = (f32::from(*channel) * (1.0 - coverage) + f32::from(target) * coverage).round() as u8;
+    }
+}
+
+/// Draw a bitmap glyph, such as a colour emoji, scaled to the font size
+fn draw_image_glyph<F: Font>(canvas: &mut RgbaImage, font: &F, glyph_id: GlyphId, scale: PxScale, x: f32, baseline: f32) {
+    let Some(units_per_em) = font.units_per_em() else {
+        return;
+    };
+    let em_px = units_per_em * font.as_scaled(scale).scale_factor().vertical;
+    let Some(image) = font.glyph_raster_image2(glyph_id, em_px.round() as u16) else {
+        return;
+    };
+    if !matches!(image.format, GlyphImageFormat::Png) || image.pixels_per_em == 0 {
+        return;
+    }
+    let Ok(decoded) = image::load_from_memory(image.data) else {
+        return;
+    };
+
+    let ratio = em_px / f32::from(image.pixels_per_em);
+    let width = (decoded.width() as f32 * ratio).round() as u32;
+    let height = (decoded.height() as f32 * ratio).round() as u32;
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    // The origin is the image's bottom-left corner relative to the glyph origin, y up
+    let resized = decoded.resize_exact(width, height, image::imageops::FilterType::Triangle).to_rgba8();
+    let left = x + image.origin.x * ratio;
+    let top = baseline - image.origin.y * ratio - height as f32;
+    image::imageops::overlay(canvas, &resized, left.round() as i64, top.round() as i64);
+}
@@ -0,0 +1,171 @@
+//! Now-playing backends
+//!
+//! A source connects to a player (Roon via the Node sidecar, ...) and turns its
+//! updates into `SourceEvent`s. Everything downstream - `AppState`, zone
+//! selection, the tray and the compositor - only sees those events, so any
+//! backend that can describe zones and tracks in the shared message format can
+//! drive the app.
+
+pub mod mpd;
+#[cfg(target_os = "linux")]
+pub mod mpris;
+pub mod replay;
+pub mod sidecar;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::types::{ConnectionStatus, SidecarCommand, SidecarMessage};
+
+/// An update from a now-playing source
+#[derive(Debug)]
+pub enum SourceEvent {
+    /// Zone, track or artwork update in the shared message format
+    Message(SidecarMessage),
+    /// Connection state changed outside the message stream (process exit, failed handshake)
+    Status(ConnectionStatus),
+    /// The source lost its player - nothing is playing
+    Disconnected,
+    /// The set of supported commands changed, so capability-gated menu items need a rebuild
+    CapabilitiesChanged,
+}
+
+/// Covers loaded by sources themselves are scaled to this size before they
+/// enter the artwork cache
+const ARTWORK_THUMBNAIL_SIZE: u32 = 128;
+
+/// Channel a source delivers its events on
+pub type EventSender = Sender<SourceEvent>;
+
+/// The active source, shared between the tray, the event dispatcher and shutdown handlers
+pub type SharedSource = Arc<dyn NowPlayingSource>;
+
+/// A player backend that feeds the app's state
+pub trait NowPlayingSource: Send + Sync {
+    /// Player name shown in logs and status messages
+    fn name(&self) -> &'static str;
+
+    /// Connect to the player and start delivering events
+    /// A source keeps reconnecting on its own after `start` succeeds
+    fn start(&self, events: EventSender) -> Result<()>;
+
+    /// Disconnect and release all resources. Safe to call more than once
+    fn stop(&self) -> Result<()>;
+
+    /// Send a transport or artwork command to the player
+    fn send_command(&self, command: SidecarCommand) -> Result<()>;
+
+    /// Check whether the source supports an optional feature (see `types::capability`)
+    fn has_capability(&self, capability: &str) -> bool;
+
+    /// How fast the source's time runs compared to the wall clock
+    /// Only recordings can be played faster or slower
+    fn speed(&self) -> f64 {
+        1.0
+    }
+}
+
+/// Create the source selected by MACAROON_SOURCE (`roon`, `mpd`, `replay` or on
+/// Linux `mpris`, default `roon`)
+/// `resource_dir` locates the bundled sidecar binary in production builds
+pub fn from_env(resource_dir: Option<PathBuf>) -> SharedSource {
+    let roon = |resource_dir| {
+        Arc::new(sidecar::SidecarManager::new(resource_dir).with_recorder(replay::Recorder::from_env()))
+    };
+
+    match std::env::var("MACAROON_SOURCE").as_deref() {
+        Ok("mpd") => Arc::new(mpd::MpdSource::new(mpd::MpdConfig::from_env())),
+        #[cfg(target_os = "linux")]
+        Ok("mpris") => Arc::new(mpris::MprisSource::new()),
+        Ok("replay") => match replay::ReplaySource::from_env() {
+            Ok(source) => Arc::new(source),
+            Err(e) => {
+                log::error!("Cannot replay: {}, falling back to Roon", e);
+                roon(resource_dir)
+            }
+        },
+        Ok("roon") | Err(_) => roon(resource_dir),
+        Ok(other) => {
+            log::warn!("Unknown source '{}', falling back to Roon", other);
+            roon(resource_dir)
+        }
+    }
+}
+
+/// Sleep for `duration` in short steps, returning early (with false) once `flag` is set
+/// Lets source threads notice stop() without waiting out a long delay
+fn sleep_unless(flag: &AtomicBool, duration: Duration) -> bool {
+    const STEP: Duration = Duration::from_millis(100);
+    let deadline = Instant::now() + duration;
+
+    loop {
+        if flag.load(Ordering::SeqCst) {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        std::thread::sleep(remaining.min(STEP));
+    }
+}
+
+/// Remembers which covers a source has sent, for sources that load artwork themselves
+#[derive(Default)]
+struct ArtworkTracker {
+    /// Where each key's artwork is loaded from (file path, URL, ...)
+    locations: HashMap<String, String>,
+    sent: HashSet<String>,
+    /// Keys with no usable artwork, so they aren't retried on every update
+    missing: HashSet<String>,
+}
+
+impl ArtworkTracker {
+    /// Record a key's location and return whether it was already handled:
+    /// `Some(true)` if sent, `Some(false)` if known missing, None if it still needs loading
+    fn lookup(&mut self, image_key: &str, location: &str) -> Option<bool> {
+        if self.sent.contains(image_key) {
+            return Some(true);
+        }
+        if self.missing.contains(image_key) {
+            return Some(false);
+        }
+        self.locations.insert(image_key.to_string(), location.to_string());
+        None
+    }
+
+    fn mark_sent(&mut self, image_key: &str) {
+        self.sent.insert(image_key.to_string());
+    }
+
+    fn mark_missing(&mut self, image_key: &str) {
+        self.missing.insert(image_key.to_string());
+    }
+
+    /// Forget the outcome for a key so it is loaded again, returning its location
+    fn reset(&mut self, image_key: &str) -> Option<String> {
+        self.sent.remove(image_key);
+        self.missing.remove(image_key);
+        self.locations.get(image_key).cloned()
+    }
+}
+
+/// Scale a cover image down and encode it as a PNG data URL for an `artwork` message
+fn artwork_data_url(image_bytes: &[u8]) -> Result<String> {
+    let image = image::load_from_memory(image_bytes).context("Failed to decode cover art")?;
+    let thumbnail = image.thumbnail(ARTWORK_THUMBNAIL_SIZE, ARTWORK_THUMBNAIL_SIZE);
+
+    let mut png = Vec::new();
+    thumbnail
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .context("Failed to encode cover art")?;
+
+    let encoded = base64::engine::general_purpose::STANDARD.encode(&png);
+    Ok(format!("data:image/png;base64,{}", encoded))
+}
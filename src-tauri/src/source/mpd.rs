@@ -0,0 +1,992 @@
+//! MPD source - speaks the MPD text protocol directly over TCP or a Unix socket
+//!
+//! Each MPD partition becomes a zone, named after the outputs it plays on.
+//! Updates are pushed with `idle`; cover art comes from `albumart` (cover file
+//! in the song's directory) with `readpicture` (embedded picture) as fallback.
+
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{
+    artwork_data_url, sleep_unless, ArtworkTracker, EventSender, NowPlayingSource, SourceEvent,
+};
+use crate::types::{
+    capability, ConnectionStatus, NowPlayingInfo, PlaybackState, SidecarCommand, SidecarMessage,
+    ZoneInfo,
+};
+
+const DEFAULT_HOST: &str = "localhost";
+const DEFAULT_PORT: u16 = 6600;
+
+/// Partition every MPD server has; also the one used before partitions existed
+const DEFAULT_PARTITION: &str = "default";
+
+/// Prefix for zone IDs, so they can't collide with another source's IDs
+const ZONE_ID_PREFIX: &str = "mpd:";
+
+/// Subsystems that wake the idle connection
+const IDLE_SUBSYSTEMS: &str = "player mixer output partition";
+
+/// `idle` only reports player changes for the connection's own partition,
+/// so other partitions are polled at this interval
+const PARTITION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Timeout for connecting and for replies to regular commands
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Configuration for reconnect backoff
+const RECONNECT_INITIAL_DELAY_MS: u64 = 1000;
+const RECONNECT_MAX_DELAY_MS: u64 = 30000;
+const RECONNECT_MULTIPLIER: u64 = 2;
+
+/// Largest cover image downloaded from MPD before it is scaled down
+const MAX_ARTWORK_DOWNLOAD: usize = 16 * 1024 * 1024;
+
+/// Where the MPD server listens
+#[derive(Debug, Clone)]
+pub enum MpdAddress {
+    Tcp { host: String, port: u16 },
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Connection settings for the MPD source
+#[derive(Debug, Clone)]
+pub struct MpdConfig {
+    pub address: MpdAddress,
+    pub password: Option<String>,
+}
+
+impl MpdConfig {
+    /// Read the standard MPD_HOST / MPD_PORT variables
+    /// MPD_HOST may be `password@host`, and a path selects a Unix socket
+    pub fn from_env() -> Self {
+        let host_var = std::env::var("MPD_HOST").unwrap_or_else(|_| DEFAULT_HOST.to_string());
+        let (password, host) = match host_var.rsplit_once('@') {
+            Some((password, host)) if !password.is_empty() => {
+                (Some(password.to_string()), host.to_string())
+            }
+            _ => (None, host_var),
+        };
+
+        let port = std::env::var("MPD_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_PORT);
+
+        #[cfg(unix)]
+        {
+            if host.starts_with('/') || host.starts_with('~') {
+                let path = match host.strip_prefix("~/") {
+                    Some(rest) => std::env::var_os("HOME")
+                        .map(|home| PathBuf::from(home).join(rest))
+                        .unwrap_or_else(|| PathBuf::from(&host)),
+                    None => PathBuf::from(&host),
+                };
+                return Self { address: MpdAddress::Unix(path), password };
+            }
+        }
+
+        Self {
+            address: MpdAddress::Tcp { host, port },
+            password,
+        }
+    }
+}
+
+/// A TCP or Unix socket connection to MPD
+enum MpdStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl MpdStream {
+    fn connect(address: &MpdAddress) -> Result<Self> {
+        match address {
+            MpdAddress::Tcp { host, port } => {
+                let socket_addr = (host.as_str(), *port)
+                    .to_socket_addrs()
+                    .with_context(|| format!("Failed to resolve MPD host {}", host))?
+                    .next()
+                    .with_context(|| format!("No address found for MPD host {}", host))?;
+                let stream = TcpStream::connect_timeout(&socket_addr, IO_TIMEOUT)
+                    .with_context(|| format!("Failed to connect to MPD at {}:{}", host, port))?;
+                Ok(Self::Tcp(stream))
+            }
+            #[cfg(unix)]
+            MpdAddress::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)
+                    .with_context(|| format!("Failed to connect to MPD socket {:?}", path))?;
+                Ok(Self::Unix(stream))
+            }
+        }
+    }
+
+    fn try_clone(&self) -> std::io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => stream.try_clone().map(Self::Tcp),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.try_clone().map(Self::Unix),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+impl Read for MpdStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for MpdStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// One line of an MPD response
+enum ResponseLine {
+    Pair(String, String),
+    /// Announces a chunk of raw bytes of the given length
+    Binary(usize),
+    Ok,
+    Ack(String),
+}
+
+/// Key/value pairs of a response, in order
+type Pairs = Vec<(String, String)>;
+
+/// Quote a command argument
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// First value for a key in a response
+fn value<'a>(pairs: &'a Pairs, key: &str) -> Option<&'a str> {
+    pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+/// A client connection speaking the MPD text protocol
+struct MpdConnection {
+    reader: BufReader<MpdStream>,
+}
+
+impl MpdConnection {
+    /// Connect, read the greeting and authenticate if a password is configured
+    fn open(config: &MpdConfig) -> Result<Self> {
+        let stream = MpdStream::connect(&config.address)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        let mut connection = Self { reader: BufReader::new(stream) };
+
+        let mut greeting = String::new();
+        connection.reader.read_line(&mut greeting).context("Failed to read MPD greeting")?;
+        let Some(version) = greeting.trim_end().strip_prefix("OK MPD ") else {
+            bail!("Unexpected MPD greeting: {}", greeting.trim_end());
+        };
+        log::info!("Connected to MPD {}", version);
+
+        if let Some(password) = &config.password {
+            connection
+                .command(&format!("password {}", quote(password)))
+                .context("MPD rejected the password")?;
+        }
+
+        Ok(connection)
+    }
+
+    fn stream(&self) -> &MpdStream {
+        self.reader.get_ref()
+    }
+
+    fn send(&mut self, line: &str) -> Result<()> {
+        let stream = self.reader.get_mut();
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\n")?;
+        stream.flush().context("Failed to write MPD command")
+    }
+
+    fn next_line(&mut self) -> Result<ResponseLine> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("MPD closed the connection");
+        }
+        let line = line.trim_end_matches('\n');
+
+        if line == "OK" {
+            return Ok(ResponseLine::Ok);
+        }
+        if let Some(error) = line.strip_prefix("ACK ") {
+            return Ok(ResponseLine::Ack(error.to_string()));
+        }
+        match line.split_once(": ") {
+            Some(("binary", length)) => {
+                let length = length.parse().context("Invalid binary length from MPD")?;
+                Ok(ResponseLine::Binary(length))
+            }
+            Some((key, value)) => Ok(ResponseLine::Pair(key.to_string(), value.to_string())),
+            None => bail!("Malformed MPD response line: {}", line),
+        }
+    }
+
+    /// Read a response made of key/value pairs
+    fn read_pairs(&mut self) -> Result<Pairs> {
+        let mut pairs = Vec::new();
+        loop {
+            match self.next_line()? {
+                ResponseLine::Pair(key, value) => pairs.push((key, value)),
+                ResponseLine::Ok => return Ok(pairs),
+                ResponseLine::Ack(error) => bail!("MPD error: {}", error),
+                ResponseLine::Binary(_) => bail!("Unexpected binary data in MPD response"),
+            }
+        }
+    }
+
+    /// Send a command and read its response
+    fn command(&mut self, line: &str) -> Result<Pairs> {
+        self.send(line)?;
+        self.read_pairs()
+    }
+
+    /// Download a picture with `albumart` or `readpicture`, which return it in chunks
+    /// Returns None if MPD has no picture for the song
+    fn read_picture(&mut self, command: &str, uri: &str) -> Result<Option<Vec<u8>>> {
+        let mut data: Vec<u8> = Vec::new();
+
+        loop {
+            self.send(&format!("{} {} {}", command, quote(uri), data.len()))?;
+
+            let mut total_size: Option<usize> = None;
+            let mut chunk_length = 0;
+            loop {
+                match self.next_line()? {
+                    ResponseLine::Pair(key, value) => {
+                        if key == "size" {
+                            total_size = value.parse().ok();
+                        }
+                    }
+                    ResponseLine::Binary(length) => {
+                        if data.len() + length > MAX_ARTWORK_DOWNLOAD {
+                            bail!("Cover art for {} exceeds {} bytes", uri, MAX_ARTWORK_DOWNLOAD);
+                        }
+                        let start = data.len();
+                        data.resize(start + length, 0);
+                        self.reader.read_exact(&mut data[start..])?;
+                        // Chunk is followed by a newline
+                        let mut newline = [0u8; 1];
+                        self.reader.read_exact(&mut newline)?;
+                        chunk_length = length;
+                    }
+                    ResponseLine::Ok => break,
+                    ResponseLine::Ack(error) => {
+                        log::debug!("No {} for {}: {}", command, uri, error);
+                        return Ok(None);
+                    }
+                }
+            }
+
+            // readpicture answers with an empty response when there is no picture
+            let Some(total_size) = total_size else {
+                return Ok(None);
+            };
+            if chunk_length == 0 || data.len() >= total_size {
+                return Ok(if data.is_empty() { None } else { Some(data) });
+            }
+        }
+    }
+
+    /// Switch this connection to a partition
+    fn enter_partition(&mut self, partition: &str) -> Result<()> {
+        self.command(&format!("partition {}", quote(partition))).map(|_| ())
+    }
+}
+
+/// Currently playing song and player state of one partition
+#[derive(Debug, Clone, PartialEq)]
+struct PartitionSnapshot {
+    name: String,
+    /// Names of enabled outputs in this partition
+    outputs: Vec<String>,
+    state: PlaybackState,
+    song: Option<Song>,
+    elapsed: Option<f64>,
+}
+
+impl PartitionSnapshot {
+    fn zone_id(&self) -> String {
+        format!("{}{}", ZONE_ID_PREFIX, self.name)
+    }
+
+    fn display_name(&self) -> String {
+        match (self.outputs.is_empty(), self.name == DEFAULT_PARTITION) {
+            (false, _) => self.outputs.join(" + "),
+            (true, true) => "MPD".to_string(),
+            (true, false) => self.name.clone(),
+        }
+    }
+
+    /// Everything except the elapsed time, which changes constantly while playing
+    fn differs_from(&self, other: &PartitionSnapshot) -> bool {
+        self.name != other.name
+            || self.outputs != other.outputs
+            || self.state != other.state
+            || self.song != other.song
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Song {
+    file: String,
+    title: String,
+    artist: String,
+    album: String,
+    duration: Option<f64>,
+}
+
+impl Song {
+    fn from_pairs(pairs: &Pairs) -> Option<Self> {
+        let file = value(pairs, "file")?.to_string();
+
+        // Untagged files fall back to their file name
+        let title = value(pairs, "Title")
+            .or_else(|| value(pairs, "Name"))
+            .map(str::to_string)
+            .unwrap_or_else(|| file.rsplit('/').next().unwrap_or(&file).to_string());
+
+        let artist = value(pairs, "Artist")
+            .or_else(|| value(pairs, "AlbumArtist"))
+            .unwrap_or_default()
+            .to_string();
+
+        Some(Self {
+            title,
+            artist,
+            album: value(pairs, "Album").unwrap_or_default().to_string(),
+            duration: value(pairs, "duration").and_then(|d| d.parse().ok()),
+            file,
+        })
+    }
+}
+
+/// Query the player state of the connection's current partition
+fn query_partition(connection: &mut MpdConnection, name: &str) -> Result<PartitionSnapshot> {
+    let status = connection.command("status")?;
+    let state = match value(&status, "state") {
+        Some("play") => PlaybackState::Playing,
+        Some("pause") => PlaybackState::Paused,
+        Some("stop") => PlaybackState::Stopped,
+        _ => PlaybackState::Unknown,
+    };
+
+    let song = if state == PlaybackState::Stopped {
+        None
+    } else {
+        Song::from_pairs(&connection.command("currentsong")?)
+    };
+
+    // Outputs that were moved to another partition show up with the "dummy" plugin
+    let outputs_response = connection.command("outputs")?;
+    let mut outputs = Vec::new();
+    let mut current: Option<(String, bool, bool)> = None;
+    for (key, value) in outputs_response {
+        match key.as_str() {
+            "outputid" => {
+                if let Some((name, true, false)) = current.take() {
+                    outputs.push(name);
+                }
+                current = Some((String::new(), false, false));
+            }
+            "outputname" => {
+                if let Some(output) = current.as_mut() {
+                    output.0 = value;
+                }
+            }
+            "outputenabled" => {
+                if let Some(output) = current.as_mut() {
+                    output.1 = value == "1";
+                }
+            }
+            "plugin" => {
+                if let Some(output) = current.as_mut() {
+                    output.2 = value == "dummy";
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, true, false)) = current {
+        outputs.push(name);
+    }
+
+    Ok(PartitionSnapshot {
+        name: name.to_string(),
+        outputs,
+        state,
+        elapsed: value(&status, "elapsed").and_then(|e| e.parse().ok()),
+        song,
+    })
+}
+
+/// Query every partition, leaving the connection in the default partition
+fn query_all(connection: &mut MpdConnection) -> Result<Vec<PartitionSnapshot>> {
+    // Servers before 0.22 have no partitions
+    let partitions: Vec<String> = match connection.command("listpartitions") {
+        Ok(pairs) => pairs
+            .into_iter()
+            .filter(|(key, _)| key == "partition")
+            .map(|(_, name)| name)
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    if partitions.len() <= 1 {
+        return Ok(vec![query_partition(connection, DEFAULT_PARTITION)?]);
+    }
+
+    let mut snapshots = Vec::with_capacity(partitions.len());
+    for partition in &partitions {
+        connection.enter_partition(partition)?;
+        snapshots.push(query_partition(connection, partition)?);
+    }
+    connection.enter_partition(DEFAULT_PARTITION)?;
+
+    Ok(snapshots)
+}
+
+/// Artwork cache key for a song
+fn artwork_key(file: &str) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    file.hash(&mut hasher);
+    format!("mpd-{:016x}", hasher.finish())
+}
+
+/// Download a song's cover and scale it down to a PNG data URL
+fn fetch_artwork(connection: &mut MpdConnection, file: &str) -> Result<Option<String>> {
+    let picture = match connection.read_picture("albumart", file)? {
+        Some(picture) => picture,
+        None => match connection.read_picture("readpicture", file)? {
+            Some(picture) => picture,
+            None => return Ok(None),
+        },
+    };
+
+    artwork_data_url(&picture)
+        .with_context(|| format!("Failed to convert cover art for {}", file))
+        .map(Some)
+}
+
+/// State shared between MpdSource and its threads
+struct MpdShared {
+    config: MpdConfig,
+    events: Mutex<Option<EventSender>>,
+    shutdown_flag: AtomicBool,
+    /// Clone of the idle connection's stream, shut down to interrupt a blocking read on stop
+    active_stream: Mutex<Option<MpdStream>>,
+    /// Song file for each artwork key, shared with command threads for `fetch_artwork`
+    artwork: Mutex<ArtworkTracker>,
+}
+
+impl MpdShared {
+    /// Deliver an event to the app
+    fn emit(&self, event: SourceEvent) {
+        if let Some(events) = self.events.lock().as_ref() {
+            if events.send(event).is_err() {
+                log::debug!("Event receiver closed, dropping MPD event");
+            }
+        }
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown_flag.load(Ordering::SeqCst)
+    }
+
+    /// Make sure the artwork for a song has been sent, returning its key if available
+    fn ensure_artwork(&self, connection: &mut MpdConnection, file: &str) -> Option<String> {
+        let key = artwork_key(file);
+        match self.artwork.lock().lookup(&key, file) {
+            Some(true) => return Some(key),
+            Some(false) => return None,
+            None => {}
+        }
+
+        match fetch_artwork(connection, file) {
+            Ok(Some(data)) => {
+                self.emit(SourceEvent::Message(SidecarMessage::Artwork {
+                    image_key: key.clone(),
+                    data,
+                }));
+                self.artwork.lock().mark_sent(&key);
+                Some(key)
+            }
+            Ok(None) => {
+                self.artwork.lock().mark_missing(&key);
+                None
+            }
+            Err(e) => {
+                log::warn!("Failed to load cover art for {}: {}", file, e);
+                self.artwork.lock().mark_missing(&key);
+                None
+            }
+        }
+    }
+
+    /// Emit the zone list and the now playing state of changed partitions
+    fn emit_snapshots(
+        &self,
+        connection: &mut MpdConnection,
+        snapshots: &[PartitionSnapshot],
+        previous: &[PartitionSnapshot],
+        only_changed: bool,
+    ) {
+        let image_keys: Vec<Option<String>> = snapshots
+            .iter()
+            .map(|snapshot| {
+                let song = snapshot.song.as_ref()?;
+                self.ensure_artwork(connection, &song.file)
+            })
+            .collect();
+
+        let zones = snapshots
+            .iter()
+            .zip(&image_keys)
+            .map(|(snapshot, image_key)| ZoneInfo {
+                zone_id: snapshot.zone_id(),
+                display_name: snapshot.display_name(),
+                state: snapshot.state.clone(),
+                now_playing: snapshot.song.as_ref().map(|song| NowPlayingInfo {
+                    title: song.title.clone(),
+                    artist: song.artist.clone(),
+                    album: song.album.clone(),
+                    image_key: image_key.clone(),
+                    seek_position: snapshot.elapsed,
+                    length: song.duration,
+                }),
+            })
+            .collect();
+        self.emit(SourceEvent::Message(SidecarMessage::ZoneList { zones }));
+
+        for (snapshot, image_key) in snapshots.iter().zip(image_keys) {
+            let Some(song) = &snapshot.song else {
+                continue;
+            };
+
+            let unchanged = previous
+                .iter()
+                .any(|p| p.name == snapshot.name && !snapshot.differs_from(p));
+            if only_changed && unchanged {
+                continue;
+            }
+
+            self.emit(SourceEvent::Message(SidecarMessage::NowPlaying {
+                zone_id: snapshot.zone_id(),
+                title: song.title.clone(),
+                artist: song.artist.clone(),
+                album: song.album.clone(),
+                state: snapshot.state.clone(),
+                image_key,
+                seek_position: snapshot.elapsed,
+                length: song.duration,
+            }));
+        }
+    }
+
+    /// Serve an open connection until it fails or the source is stopped
+    fn run_connection(&self, mut connection: MpdConnection) -> Result<()> {
+        *self.active_stream.lock() = Some(connection.stream().try_clone()?);
+        self.emit(SourceEvent::Status(ConnectionStatus::Connected));
+
+        let mut previous: Vec<PartitionSnapshot> = Vec::new();
+        let mut only_changed = false;
+
+        while !self.is_shutting_down() {
+            let snapshots = query_all(&mut connection)?;
+            self.emit_snapshots(&mut connection, &snapshots, &previous, only_changed);
+
+            // Other partitions are invisible to idle, so wake up periodically to poll them
+            let poll_timeout = (snapshots.len() > 1).then_some(PARTITION_POLL_INTERVAL);
+            previous = snapshots;
+
+            connection.send(&format!("idle {}", IDLE_SUBSYSTEMS))?;
+            connection.stream().set_read_timeout(poll_timeout)?;
+            let woke = connection.read_pairs();
+            connection.stream().set_read_timeout(Some(IO_TIMEOUT))?;
+
+            match woke {
+                Ok(changed) => {
+                    log::debug!("MPD idle woke: {:?}", changed);
+                    only_changed = false;
+                }
+                Err(e) if is_timeout(&e) => {
+                    // Poll: cancel the idle, then re-query
+                    connection.command("noidle")?;
+                    only_changed = true;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keep a connection open, reconnecting with backoff, until the source is stopped
+    fn run(&self) {
+        let mut attempt: u32 = 0;
+
+        while !self.is_shutting_down() {
+            if attempt == 0 {
+                self.emit(SourceEvent::Status(ConnectionStatus::Discovering));
+            }
+
+            let mut connected = false;
+            let result = MpdConnection::open(&self.config).and_then(|connection| {
+                connected = true;
+                self.run_connection(connection)
+            });
+
+            match result {
+                Ok(()) => break,
+                Err(e) => {
+                    if self.is_shutting_down() {
+                        break;
+                    }
+                    log::warn!("MPD connection failed: {}", e);
+                    self.emit(SourceEvent::Disconnected);
+                    self.emit(SourceEvent::Status(ConnectionStatus::Disconnected));
+                }
+            }
+            *self.active_stream.lock() = None;
+
+            // Backoff only grows across failed connects; losing a working
+            // connection starts over with a fresh round of discovery
+            if connected {
+                attempt = 0;
+            }
+            let delay_ms = RECONNECT_INITIAL_DELAY_MS * RECONNECT_MULTIPLIER.saturating_pow(attempt);
+            let delay = Duration::from_millis(delay_ms.min(RECONNECT_MAX_DELAY_MS));
+            attempt = if connected { 0 } else { attempt.saturating_add(1) };
+            log::info!("Reconnecting to MPD in {:?}", delay);
+
+            sleep_unless(&self.shutdown_flag, delay);
+        }
+
+        log::debug!("MPD event thread stopped");
+    }
+
+    /// Run a command on its own connection
+    fn run_command(&self, command: SidecarCommand) -> Result<()> {
+        let mut connection = MpdConnection::open(&self.config)?;
+
+        let zone_id = match &command {
+            SidecarCommand::Play { zone_id }
+            | SidecarCommand::Pause { zone_id }
+            | SidecarCommand::PlayPause { zone_id }
+            | SidecarCommand::Next { zone_id }
+            | SidecarCommand::Previous { zone_id }
+            | SidecarCommand::Stop { zone_id }
+            | SidecarCommand::Seek { zone_id, .. } => Some(zone_id.as_str()),
+            SidecarCommand::FetchArtwork { .. } => None,
+        };
+        if let Some(zone_id) = zone_id {
+            let partition = zone_id
+                .strip_prefix(ZONE_ID_PREFIX)
+                .with_context(|| format!("Zone {} does not belong to MPD", zone_id))?;
+            if partition != DEFAULT_PARTITION {
+                connection.enter_partition(partition)?;
+            }
+        }
+
+        match command {
+            SidecarCommand::Play { .. } => connection.command("play")?,
+            SidecarCommand::Pause { .. } => connection.command("pause 1")?,
+            SidecarCommand::PlayPause { .. } => {
+                let status = connection.command("status")?;
+                match value(&status, "state") {
+                    Some("play") => connection.command("pause 1")?,
+                    Some("pause") => connection.command("pause 0")?,
+                    _ => connection.command("play")?,
+                }
+            }
+            SidecarCommand::Next { .. } => connection.command("next")?,
+            SidecarCommand::Previous { .. } => connection.command("previous")?,
+            SidecarCommand::Stop { .. } => connection.command("stop")?,
+            SidecarCommand::Seek { seconds, .. } => connection.command(&format!("seekcur {}", seconds))?,
+            SidecarCommand::FetchArtwork { image_key } => {
+                let file = self.artwork.lock().reset(&image_key)
+                    .with_context(|| format!("Unknown artwork key {}", image_key))?;
+                if self.ensure_artwork(&mut connection, &file).is_none() {
+                    bail!("Artwork not available for {}", image_key);
+                }
+                Vec::new()
+            }
+        };
+
+        Ok(())
+    }
+}
+
+/// Check whether an error is a read timeout
+fn is_timeout(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut))
+}
+
+/// Now-playing source backed by an MPD server
+pub struct MpdSource {
+    shared: Arc<MpdShared>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MpdSource {
+    pub fn new(config: MpdConfig) -> Self {
+        Self {
+            shared: Arc::new(MpdShared {
+                config,
+                events: Mutex::new(None),
+                shutdown_flag: AtomicBool::new(false),
+                active_stream: Mutex::new(None),
+                artwork: Mutex::new(ArtworkTracker::default()),
+            }),
+            worker: Mutex::new(None),
+        }
+    }
+}
+
+impl NowPlayingSource for MpdSource {
+    fn name(&self) -> &'static str {
+        "MPD"
+    }
+
+    fn start(&self, events: EventSender) -> Result<()> {
+        *self.shared.events.lock() = Some(events);
+        self.shared.shutdown_flag.store(false, Ordering::SeqCst);
+
+        let shared = self.shared.clone();
+        let handle = thread::Builder::new()
+            .name("mpd-events".to_string())
+            .spawn(move || shared.run())
+            .context("Failed to spawn MPD event thread")?;
+        *self.worker.lock() = Some(handle);
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        self.shared.shutdown_flag.store(true, Ordering::SeqCst);
+
+        // Unblock the idle read
+        if let Some(stream) = self.shared.active_stream.lock().take() {
+            let _ = stream.shutdown();
+        }
+
+        if let Some(handle) = self.worker.lock().take() {
+            if handle.join().is_err() {
+                log::warn!("Failed to join MPD event thread");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_command(&self, command: SidecarCommand) -> Result<()> {
+        // Commands use their own short-lived connection so they don't block the idle loop
+        // (or the caller - menu clicks arrive on the main thread)
+        let shared = self.shared.clone();
+        let name = command.name();
+        thread::Builder::new()
+            .name("mpd-command".to_string())
+            .spawn(move || {
+                if let Err(e) = shared.run_command(command) {
+                    log::error!("MPD command {} failed: {}", name, e);
+                }
+            })
+            .context("Failed to spawn MPD command thread")?;
+
+        Ok(())
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(
+            capability,
+            capability::TRANSPORT_CONTROL | capability::SEEK | capability::FETCH_ARTWORK
+        )
+    }
+}
+
+impl Drop for MpdSource {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            log::error!("Error stopping MPD source in Drop: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Instant;
+
+    /// Serve `sessions` scripted MPD connections, one after another
+    /// Every session but the last is dropped as soon as the client idles;
+    /// the last one stays open until the client goes away
+    fn fake_server(sessions: usize) -> (u16, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            for session in 0..sessions {
+                let (stream, _) = listener.accept().unwrap();
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                writer.write_all(b"OK MPD 0.23.0\n").unwrap();
+
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    let command = line.split_whitespace().next().unwrap_or_default().to_string();
+                    line.clear();
+                    let reply: &[u8] = match command.as_str() {
+                        "listpartitions" => b"partition: default\nOK\n",
+                        "status" => b"volume: 50\nstate: play\nelapsed: 12.5\nOK\n",
+                        "currentsong" => b"file: music/song.flac\nTitle: Song\nArtist: Band\nAlbum: Record\nduration: 200.0\nOK\n",
+                        "outputs" => b"outputid: 0\noutputname: Speakers\noutputenabled: 1\nplugin: alsa\nOK\n",
+                        "albumart" => b"ACK [50@0] {albumart} No file exists\n",
+                        "readpicture" => b"OK\n",
+                        "idle" if session + 1 < sessions => break,
+                        "idle" => continue,
+                        _ => b"ACK [5@0] {} unknown command\n",
+                    };
+                    writer.write_all(reply).unwrap();
+                }
+            }
+        });
+
+        (port, handle)
+    }
+
+    fn start_source(port: u16) -> (MpdSource, Receiver<SourceEvent>) {
+        let source = MpdSource::new(MpdConfig {
+            address: MpdAddress::Tcp { host: "127.0.0.1".to_string(), port },
+            password: None,
+        });
+        let (sender, receiver) = mpsc::channel();
+        source.start(sender).unwrap();
+        (source, receiver)
+    }
+
+    /// Short names for the events, with the time each arrived
+    fn collect(receiver: &Receiver<SourceEvent>, until: &str, count: usize) -> Vec<(String, Instant)> {
+        let mut events = Vec::new();
+        let mut seen = 0;
+        while seen < count {
+            let event = receiver.recv_timeout(Duration::from_secs(10)).expect("MPD source went quiet");
+            let name = match event {
+                SourceEvent::Status(status) => format!("status:{:?}", status),
+                SourceEvent::Message(SidecarMessage::ZoneList { zones }) => {
+                    format!("zones:{}", zones.iter().map(|z| z.display_name.as_str()).collect::<Vec<_>>().join(","))
+                }
+                SourceEvent::Message(SidecarMessage::NowPlaying { zone_id, title, artist, state, .. }) => {
+                    format!("now_playing:{}:{} - {}:{:?}", zone_id, title, artist, state)
+                }
+                SourceEvent::Message(other) => format!("{:?}", other),
+                SourceEvent::Disconnected => "disconnected".to_string(),
+                SourceEvent::CapabilitiesChanged => "capabilities".to_string(),
+            };
+            if name == until {
+                seen += 1;
+            }
+            events.push((name, Instant::now()));
+        }
+        events
+    }
+
+    fn names(events: &[(String, Instant)]) -> Vec<&str> {
+        events.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn reports_the_current_song_and_reconnects_after_a_drop() {
+        let (port, server) = fake_server(2);
+        let (source, receiver) = start_source(port);
+
+        let events = collect(&receiver, "status:Connected", 2);
+        let events = [events, collect(&receiver, "now_playing:mpd:default:Song - Band:Playing", 1)].concat();
+        source.stop().unwrap();
+        server.join().unwrap();
+
+        let session = [
+            "status:Discovering",
+            "status:Connected",
+            "zones:Speakers",
+            "now_playing:mpd:default:Song - Band:Playing",
+        ];
+        let expected: Vec<&str> = session.iter()
+            .chain(&["disconnected", "status:Disconnected"])
+            .chain(&session)
+            .copied()
+            .collect();
+        assert_eq!(names(&events), expected);
+    }
+
+    #[test]
+    fn backoff_restarts_after_a_working_connection() {
+        let (port, server) = fake_server(4);
+        let (source, receiver) = start_source(port);
+
+        let events = collect(&receiver, "status:Connected", 4);
+        source.stop().unwrap();
+        server.join().unwrap();
+
+        // Each drop follows a good connection, so every reconnect waits the
+        // initial delay instead of doubling, and discovery starts over
+        let names = names(&events);
+        assert_eq!(names.iter().filter(|name| **name == "status:Discovering").count(), 4);
+        let drops: Vec<Instant> = events.iter().filter(|(name, _)| name == "disconnected").map(|(_, at)| *at).collect();
+        let connects: Vec<Instant> = events.iter().filter(|(name, _)| name == "status:Connected").map(|(_, at)| *at).collect();
+        for (dropped, reconnected) in drops.iter().zip(&connects[1..]) {
+            let waited = reconnected.duration_since(*dropped);
+            assert!(waited < Duration::from_millis(RECONNECT_INITIAL_DELAY_MS * 3 / 2), "waited {:?}", waited);
+        }
+    }
+}
@@ -0,0 +1,939 @@
+//! MPRIS source - shows the MPRIS2 players on the D-Bus session bus (Linux)
+//!
+//! Every `org.mpris.MediaPlayer2.*` bus name becomes a zone. Players are polled
+//! once a second with a single `GetAll`: many players don't signal every change
+//! (and none signal Position), so polling is the one approach that works for all.
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use zbus::blocking::fdo::{DBusProxy, PropertiesProxy};
+use zbus::blocking::{Connection, Proxy};
+use zbus::names::InterfaceName;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+use super::{
+    artwork_data_url, sleep_unless, ArtworkTracker, EventSender, NowPlayingSource, SourceEvent,
+};
+use crate::types::{
+    capability, ConnectionStatus, NowPlayingInfo, PlaybackState, SidecarCommand, SidecarMessage,
+    ZoneInfo,
+};
+
+/// Bus names of MPRIS players start with this
+const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Prefix for zone IDs, so they can't collide with another source's IDs
+const ZONE_ID_PREFIX: &str = "mpris:";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The app interpolates the position itself, so only jumps beyond this are sent
+const SEEK_DRIFT_THRESHOLD_S: f64 = 2.0;
+
+/// Largest cover file read from disk before it is scaled down
+const MAX_ARTWORK_FILE: u64 = 16 * 1024 * 1024;
+
+/// Track metadata of one player
+#[derive(Debug, Clone, PartialEq)]
+struct Track {
+    track_id: Option<String>,
+    title: String,
+    artist: String,
+    album: String,
+    length: Option<f64>,
+    art_url: Option<String>,
+}
+
+/// State of one player as of the last poll
+#[derive(Debug, Clone)]
+struct PlayerSnapshot {
+    bus_name: String,
+    identity: String,
+    state: PlaybackState,
+    track: Option<Track>,
+    position: Option<f64>,
+}
+
+impl PlayerSnapshot {
+    fn zone_id(&self) -> String {
+        format!("{}{}", ZONE_ID_PREFIX, self.bus_name)
+    }
+
+    /// Everything except the position, which changes constantly while playing
+    fn differs_from(&self, other: &PlayerSnapshot) -> bool {
+        self.identity != other.identity || self.state != other.state || self.track != other.track
+    }
+}
+
+/// Position last sent to the app for a player, used to detect seeks
+struct ReportedPosition {
+    position: f64,
+    at: Instant,
+    playing: bool,
+}
+
+impl ReportedPosition {
+    fn expected_now(&self) -> f64 {
+        if self.playing {
+            self.position + self.at.elapsed().as_secs_f64()
+        } else {
+            self.position
+        }
+    }
+}
+
+fn string_value(value: &Value<'_>) -> Option<String> {
+    <&str>::try_from(value).ok().map(str::to_string)
+}
+
+/// Read an MPRIS time value (microseconds, signed or unsigned depending on the player) as seconds
+fn seconds_value(value: &Value<'_>) -> Option<f64> {
+    let micros = i64::try_from(value)
+        .ok()
+        .or_else(|| u64::try_from(value).ok().and_then(|v| i64::try_from(v).ok()))?;
+    (micros >= 0).then(|| micros as f64 / 1_000_000.0)
+}
+
+impl Track {
+    fn from_metadata(metadata: &HashMap<String, OwnedValue>) -> Option<Self> {
+        let get = |key: &str| metadata.get(key).map(|value| &**value);
+
+        let title = get("xesam:title").and_then(string_value).unwrap_or_default();
+        if title.is_empty() && get("mpris:trackid").is_none() {
+            return None;
+        }
+
+        // xesam:artist is a list; a few players send a plain string
+        let artist = get("xesam:artist")
+            .and_then(|value| match value {
+                Value::Array(array) => Some(
+                    array
+                        .iter()
+                        .filter_map(string_value)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                value => string_value(value),
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            track_id: get("mpris:trackid")
+                .and_then(|value| ObjectPath::try_from(value).ok())
+                .map(|path| path.to_string()),
+            title,
+            artist,
+            album: get("xesam:album").and_then(string_value).unwrap_or_default(),
+            length: get("mpris:length").and_then(seconds_value),
+            art_url: get("mpris:artUrl").and_then(string_value).filter(|url| !url.is_empty()),
+        })
+    }
+}
+
+/// Decode a `file://` URL into a path
+/// Other schemes (typically http) are not loaded
+fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    let encoded = url.strip_prefix("file://")?;
+    // An empty host and "localhost" both mean this machine
+    let encoded = encoded
+        .strip_prefix("localhost")
+        .filter(|rest| rest.starts_with('/'))
+        .unwrap_or(encoded);
+
+    let hex = |byte: u8| char::from(byte).to_digit(16);
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(high), Some(low)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                decoded.push((high * 16 + low) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    let path = PathBuf::from(String::from_utf8(decoded).ok()?);
+    path.is_absolute().then_some(path)
+}
+
+/// Artwork cache key for a cover URL
+fn artwork_key(art_url: &str) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    art_url.hash(&mut hasher);
+    format!("mpris-{:016x}", hasher.finish())
+}
+
+/// Load a cover from a `file://` URL as a data URL
+fn load_artwork(art_url: &str) -> Result<Option<String>> {
+    let Some(path) = file_url_to_path(art_url) else {
+        log::debug!("Skipping non-file artwork URL {}", art_url);
+        return Ok(None);
+    };
+
+    let size = std::fs::metadata(&path)
+        .with_context(|| format!("Failed to read cover file {:?}", path))?
+        .len();
+    if size > MAX_ARTWORK_FILE {
+        anyhow::bail!("Cover file {:?} is {} bytes, exceeds {}", path, size, MAX_ARTWORK_FILE);
+    }
+
+    let bytes = std::fs::read(&path).with_context(|| format!("Failed to read cover file {:?}", path))?;
+    artwork_data_url(&bytes).map(Some)
+}
+
+/// Query a player's state with one GetAll call
+fn query_player(connection: &Connection, bus_name: &str, identity: String) -> Result<PlayerSnapshot> {
+    let properties = PropertiesProxy::builder(connection)
+        .destination(bus_name)?
+        .path(MPRIS_PATH)?
+        .build()?;
+    let values = properties.get_all(InterfaceName::from_static_str_unchecked(PLAYER_INTERFACE))?;
+
+    let state = match values.get("PlaybackStatus").and_then(|value| string_value(value)).as_deref() {
+        Some("Playing") => PlaybackState::Playing,
+        Some("Paused") => PlaybackState::Paused,
+        Some("Stopped") => PlaybackState::Stopped,
+        _ => PlaybackState::Unknown,
+    };
+
+    let track = values
+        .get("Metadata")
+        .and_then(|value| value.try_clone().ok())
+        .and_then(|value| HashMap::<String, OwnedValue>::try_from(value).ok())
+        .and_then(|metadata| Track::from_metadata(&metadata));
+
+    Ok(PlayerSnapshot {
+        bus_name: bus_name.to_string(),
+        identity,
+        state,
+        position: values.get("Position").and_then(|value| seconds_value(value)),
+        track,
+    })
+}
+
+/// Human readable player name, e.g. "VLC media player"
+fn query_identity(connection: &Connection, bus_name: &str) -> String {
+    let identity = PropertiesProxy::builder(connection)
+        .destination(bus_name)
+        .and_then(|builder| builder.path(MPRIS_PATH))
+        .and_then(|builder| builder.build())
+        .and_then(|properties| {
+            properties
+                .get(InterfaceName::from_static_str_unchecked(ROOT_INTERFACE), "Identity")
+                .map_err(Into::into)
+        })
+        .ok()
+        .and_then(|value| string_value(&value));
+
+    // Fall back to the bus name suffix ("spotify", "vlc.instance1234")
+    identity.unwrap_or_else(|| bus_name.trim_start_matches(BUS_NAME_PREFIX).to_string())
+}
+
+/// State shared between MprisSource and its threads
+struct MprisShared {
+    events: Mutex<Option<EventSender>>,
+    shutdown_flag: AtomicBool,
+    connection: Mutex<Option<Connection>>,
+    /// Cover URL for each artwork key, shared with command threads for `fetch_artwork`
+    artwork: Mutex<ArtworkTracker>,
+}
+
+impl MprisShared {
+    /// Deliver an event to the app
+    fn emit(&self, event: SourceEvent) {
+        if let Some(events) = self.events.lock().as_ref() {
+            if events.send(event).is_err() {
+                log::debug!("Event receiver closed, dropping MPRIS event");
+            }
+        }
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown_flag.load(Ordering::SeqCst)
+    }
+
+    /// Make sure the artwork for a cover URL has been sent, returning its key if available
+    fn ensure_artwork(&self, art_url: &str) -> Option<String> {
+        let key = artwork_key(art_url);
+        match self.artwork.lock().lookup(&key, art_url) {
+            Some(true) => return Some(key),
+            Some(false) => return None,
+            None => {}
+        }
+
+        match load_artwork(art_url) {
+            Ok(Some(data)) => {
+                self.emit(SourceEvent::Message(SidecarMessage::Artwork {
+                    image_key: key.clone(),
+                    data,
+                }));
+                self.artwork.lock().mark_sent(&key);
+                Some(key)
+            }
+            Ok(None) => {
+                self.artwork.lock().mark_missing(&key);
+                None
+            }
+            Err(e) => {
+                log::warn!("Failed to load cover art {}: {}", art_url, e);
+                self.artwork.lock().mark_missing(&key);
+                None
+            }
+        }
+    }
+
+    /// Poll every player on the bus
+    fn poll(
+        &self,
+        connection: &Connection,
+        identities: &mut HashMap<String, String>,
+    ) -> Result<Vec<PlayerSnapshot>> {
+        let mut bus_names: Vec<String> = DBusProxy::new(connection)?
+            .list_names()?
+            .into_iter()
+            .map(|name| name.to_string())
+            .filter(|name| name.starts_with(BUS_NAME_PREFIX))
+            .collect();
+        bus_names.sort();
+
+        identities.retain(|name, _| bus_names.contains(name));
+
+        let mut snapshots = Vec::with_capacity(bus_names.len());
+        for bus_name in bus_names {
+            let identity = identities
+                .entry(bus_name.clone())
+                .or_insert_with(|| query_identity(connection, &bus_name))
+                .clone();
+
+            // A player that exits mid-poll just drops out of this round
+            match query_player(connection, &bus_name, identity) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => log::debug!("Failed to query MPRIS player {}: {}", bus_name, e),
+            }
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Send the zone list, now playing and seek updates that differ from the previous poll
+    fn emit_changes(
+        &self,
+        snapshots: &[PlayerSnapshot],
+        previous: &[PlayerSnapshot],
+        reported: &mut HashMap<String, ReportedPosition>,
+    ) {
+        let image_keys: Vec<Option<String>> = snapshots
+            .iter()
+            .map(|snapshot| {
+                let art_url = snapshot.track.as_ref()?.art_url.as_deref()?;
+                self.ensure_artwork(art_url)
+            })
+            .collect();
+
+        let zones_changed = snapshots.len() != previous.len()
+            || snapshots.iter().zip(previous).any(|(new, old)| {
+                new.bus_name != old.bus_name || new.differs_from(old)
+            });
+        if zones_changed {
+            let zones = snapshots
+                .iter()
+                .zip(&image_keys)
+                .map(|(snapshot, image_key)| ZoneInfo {
+                    zone_id: snapshot.zone_id(),
+                    display_name: snapshot.identity.clone(),
+                    state: snapshot.state.clone(),
+                    now_playing: snapshot.track.as_ref().map(|track| NowPlayingInfo {
+                        title: track.title.clone(),
+                        artist: track.artist.clone(),
+                        album: track.album.clone(),
+                        image_key: image_key.clone(),
+                        seek_position: snapshot.position,
+                        length: track.length,
+                    }),
+                })
+                .collect();
+            self.emit(SourceEvent::Message(SidecarMessage::ZoneList { zones }));
+        }
+
+        reported.retain(|bus_name, _| snapshots.iter().any(|s| &s.bus_name == bus_name));
+
+        for (snapshot, image_key) in snapshots.iter().zip(image_keys) {
+            let Some(track) = &snapshot.track else {
+                continue;
+            };
+
+            let changed = !previous
+                .iter()
+                .any(|p| p.bus_name == snapshot.bus_name && !snapshot.differs_from(p));
+
+            if changed {
+                self.emit(SourceEvent::Message(SidecarMessage::NowPlaying {
+                    zone_id: snapshot.zone_id(),
+                    title: track.title.clone(),
+                    artist: track.artist.clone(),
+                    album: track.album.clone(),
+                    state: snapshot.state.clone(),
+                    image_key,
+                    seek_position: snapshot.position,
+                    length: track.length,
+                }));
+            } else if let Some(position) = snapshot.position {
+                let drifted = match reported.get(&snapshot.bus_name) {
+                    Some(last) => (position - last.expected_now()).abs() > SEEK_DRIFT_THRESHOLD_S,
+                    None => true,
+                };
+                if !drifted {
+                    continue;
+                }
+                self.emit(SourceEvent::Message(SidecarMessage::SeekChanged {
+                    zone_id: snapshot.zone_id(),
+                    seek_position: position,
+                }));
+            } else {
+                continue;
+            }
+
+            if let Some(position) = snapshot.position {
+                reported.insert(snapshot.bus_name.clone(), ReportedPosition {
+                    position,
+                    at: Instant::now(),
+                    playing: snapshot.state == PlaybackState::Playing,
+                });
+            }
+        }
+    }
+
+    /// Poll the session bus until the source is stopped
+    fn run(&self) {
+        let connection = match Connection::session() {
+            Ok(connection) => connection,
+            Err(e) => {
+                log::error!("Failed to connect to the D-Bus session bus: {}", e);
+                self.emit(SourceEvent::Status(ConnectionStatus::Error(format!(
+                    "No D-Bus session bus: {}",
+                    e
+                ))));
+                return;
+            }
+        };
+        *self.connection.lock() = Some(connection.clone());
+        self.emit(SourceEvent::Status(ConnectionStatus::Connected));
+
+        let mut identities: HashMap<String, String> = HashMap::new();
+        let mut previous: Vec<PlayerSnapshot> = Vec::new();
+        let mut reported: HashMap<String, ReportedPosition> = HashMap::new();
+
+        while !self.is_shutting_down() {
+            match self.poll(&connection, &mut identities) {
+                Ok(snapshots) => {
+                    self.emit_changes(&snapshots, &previous, &mut reported);
+                    previous = snapshots;
+                }
+                Err(e) => log::warn!("Failed to list MPRIS players: {}", e),
+            }
+
+            sleep_unless(&self.shutdown_flag, POLL_INTERVAL);
+        }
+
+        *self.connection.lock() = None;
+        log::debug!("MPRIS poll thread stopped");
+    }
+
+    /// Call a player method for a command
+    fn run_command(&self, command: SidecarCommand) -> Result<()> {
+        if let SidecarCommand::FetchArtwork { image_key } = &command {
+            let art_url = self.artwork.lock().reset(image_key)
+                .with_context(|| format!("Unknown artwork key {}", image_key))?;
+            if self.ensure_artwork(&art_url).is_none() {
+                anyhow::bail!("Artwork not available for {}", image_key);
+            }
+            return Ok(());
+        }
+
+        let connection = self.connection.lock().clone().context("Not connected to D-Bus")?;
+
+        let (zone_id, method) = match &command {
+            SidecarCommand::Play { zone_id } => (zone_id, "Play"),
+            SidecarCommand::Pause { zone_id } => (zone_id, "Pause"),
+            SidecarCommand::PlayPause { zone_id } => (zone_id, "PlayPause"),
+            SidecarCommand::Next { zone_id } => (zone_id, "Next"),
+            SidecarCommand::Previous { zone_id } => (zone_id, "Previous"),
+            SidecarCommand::Stop { zone_id } => (zone_id, "Stop"),
+            SidecarCommand::Seek { zone_id, .. } => (zone_id, "SetPosition"),
+            SidecarCommand::FetchArtwork { .. } => unreachable!("handled above"),
+        };
+        let bus_name = zone_id
+            .strip_prefix(ZONE_ID_PREFIX)
+            .with_context(|| format!("Zone {} does not belong to MPRIS", zone_id))?;
+
+        let player = Proxy::new(&connection, bus_name, MPRIS_PATH, PLAYER_INTERFACE)?;
+
+        if let SidecarCommand::Seek { seconds, .. } = command {
+            let target_micros = i64::from(seconds) * 1_000_000;
+            let snapshot = query_player(&connection, bus_name, String::new())?;
+
+            // SetPosition needs the track ID; players without one only support relative Seek
+            match snapshot.track.and_then(|track| track.track_id) {
+                Some(track_id) => {
+                    let track_path = ObjectPath::try_from(track_id.as_str())?;
+                    player.call::<_, _, ()>("SetPosition", &(track_path, target_micros))?;
+                }
+                None => {
+                    let current_micros = (snapshot.position.unwrap_or(0.0) * 1_000_000.0) as i64;
+                    player.call::<_, _, ()>("Seek", &(target_micros - current_micros))?;
+                }
+            }
+            return Ok(());
+        }
+
+        player.call::<_, _, ()>(method, &())?;
+        Ok(())
+    }
+}
+
+/// Now-playing source showing the MPRIS2 players on the session bus
+pub struct MprisSource {
+    shared: Arc<MprisShared>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MprisSource {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(MprisShared {
+                events: Mutex::new(None),
+                shutdown_flag: AtomicBool::new(false),
+                connection: Mutex::new(None),
+                artwork: Mutex::new(ArtworkTracker::default()),
+            }),
+            worker: Mutex::new(None),
+        }
+    }
+}
+
+impl NowPlayingSource for MprisSource {
+    fn name(&self) -> &'static str {
+        "MPRIS"
+    }
+
+    fn start(&self, events: EventSender) -> Result<()> {
+        *self.shared.events.lock() = Some(events);
+        self.shared.shutdown_flag.store(false, Ordering::SeqCst);
+
+        let shared = self.shared.clone();
+        let handle = thread::Builder::new()
+            .name("mpris-poll".to_string())
+            .spawn(move || shared.run())
+            .context("Failed to spawn MPRIS poll thread")?;
+        *self.worker.lock() = Some(handle);
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        self.shared.shutdown_flag.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.worker.lock().take() {
+            if handle.join().is_err() {
+                log::warn!("Failed to join MPRIS poll thread");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_command(&self, command: SidecarCommand) -> Result<()> {
+        // D-Bus calls can block on an unresponsive player, so keep them off the caller's thread
+        // (menu clicks arrive on the main thread)
+        let shared = self.shared.clone();
+        let name = command.name();
+        thread::Builder::new()
+            .name("mpris-command".to_string())
+            .spawn(move || {
+                if let Err(e) = shared.run_command(command) {
+                    log::error!("MPRIS command {} failed: {}", name, e);
+                }
+            })
+            .context("Failed to spawn MPRIS command thread")?;
+
+        Ok(())
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(
+            capability,
+            capability::TRANSPORT_CONTROL | capability::SEEK | capability::FETCH_ARTWORK
+        )
+    }
+}
+
+impl Drop for MprisSource {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            log::error!("Error stopping MPRIS source in Drop: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Receiver};
+
+    #[test]
+    fn file_urls_decode_to_absolute_paths() {
+        assert_eq!(file_url_to_path("file:///home/me/cover.jpg"), Some(PathBuf::from("/home/me/cover.jpg")));
+        assert_eq!(file_url_to_path("file://localhost/tmp/a.png"), Some(PathBuf::from("/tmp/a.png")));
+        assert_eq!(
+            file_url_to_path("file:///music/Sigur%20R%C3%B3s/%C3%81g%C3%A6tis%20byrjun.jpg"),
+            Some(PathBuf::from("/music/Sigur Rós/Ágætis byrjun.jpg"))
+        );
+        // Lower case escapes, and a literal percent that isn't an escape
+        assert_eq!(file_url_to_path("file:///tmp/%e2%80%94/100%.jpg"), Some(PathBuf::from("/tmp/\u{2014}/100%.jpg")));
+    }
+
+    #[test]
+    fn unescaped_multibyte_characters_pass_through() {
+        assert_eq!(file_url_to_path("file:///音楽/ジャケット.png"), Some(PathBuf::from("/音楽/ジャケット.png")));
+        // A percent sign followed by multibyte text must not be sliced mid-character
+        assert_eq!(file_url_to_path("file:///a/%é.png"), Some(PathBuf::from("/a/%é.png")));
+        assert_eq!(file_url_to_path("file:///a/%aé"), Some(PathBuf::from("/a/%aé")));
+        assert_eq!(file_url_to_path("file:///a/%"), Some(PathBuf::from("/a/%")));
+    }
+
+    #[test]
+    fn other_urls_are_not_loaded() {
+        assert_eq!(file_url_to_path("https://example.com/cover.jpg"), None);
+        assert_eq!(file_url_to_path("/home/me/cover.jpg"), None);
+        assert_eq!(file_url_to_path("file:cover.jpg"), None);
+        // Relative paths and remote hosts
+        assert_eq!(file_url_to_path("file://cover.jpg"), None);
+        assert_eq!(file_url_to_path("file://localhost"), None);
+        assert_eq!(file_url_to_path("file://server/share/cover.jpg"), None);
+        // Escapes that don't decode to UTF-8
+        assert_eq!(file_url_to_path("file:///tmp/%ff.jpg"), None);
+    }
+
+    fn owned(value: impl Into<Value<'static>>) -> OwnedValue {
+        OwnedValue::try_from(value.into()).unwrap()
+    }
+
+    fn metadata(entries: Vec<(&str, OwnedValue)>) -> HashMap<String, OwnedValue> {
+        entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect()
+    }
+
+    fn track(title: &str) -> Track {
+        Track {
+            track_id: None,
+            title: title.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            length: Some(200.0),
+            art_url: None,
+        }
+    }
+
+    fn snapshot(bus_name: &str, state: PlaybackState, track: Option<Track>, position: Option<f64>) -> PlayerSnapshot {
+        PlayerSnapshot {
+            bus_name: bus_name.to_string(),
+            identity: "Player".to_string(),
+            state,
+            track,
+            position,
+        }
+    }
+
+    #[test]
+    fn metadata_maps_to_a_track() {
+        let track = Track::from_metadata(&metadata(vec![
+            ("mpris:trackid", owned(ObjectPath::try_from("/org/mpd/Tracks/7").unwrap())),
+            ("xesam:title", owned("Hoppípolla")),
+            ("xesam:artist", owned(vec!["Sigur Rós", "Amiina"])),
+            ("xesam:album", owned("Takk...")),
+            ("mpris:length", owned(268_000_000i64)),
+            ("mpris:artUrl", owned("file:///covers/takk.jpg")),
+        ]))
+        .unwrap();
+
+        assert_eq!(track.track_id.as_deref(), Some("/org/mpd/Tracks/7"));
+        assert_eq!(track.title, "Hoppípolla");
+        assert_eq!(track.artist, "Sigur Rós, Amiina");
+        assert_eq!(track.album, "Takk...");
+        assert_eq!(track.length, Some(268.0));
+        assert_eq!(track.art_url.as_deref(), Some("file:///covers/takk.jpg"));
+    }
+
+    #[test]
+    fn metadata_quirks() {
+        // A plain string artist, an unsigned length and an empty cover URL
+        let track = Track::from_metadata(&metadata(vec![
+            ("xesam:title", owned("Song")),
+            ("xesam:artist", owned("Band")),
+            ("mpris:length", owned(5_000_000u64)),
+            ("mpris:artUrl", owned("")),
+        ]))
+        .unwrap();
+        assert_eq!(track.artist, "Band");
+        assert_eq!(track.length, Some(5.0));
+        assert_eq!(track.art_url, None);
+
+        // Negative lengths are meaningless
+        let track = Track::from_metadata(&metadata(vec![
+            ("xesam:title", owned("Song")),
+            ("mpris:length", owned(-1i64)),
+        ]))
+        .unwrap();
+        assert_eq!(track.length, None);
+
+        // No title and no track ID means nothing is loaded
+        assert_eq!(Track::from_metadata(&HashMap::new()), None);
+        assert_eq!(Track::from_metadata(&metadata(vec![("xesam:album", owned("Album"))])), None);
+    }
+
+    fn shared() -> (MprisShared, Receiver<SourceEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        let shared = MprisShared {
+            events: Mutex::new(Some(sender)),
+            shutdown_flag: AtomicBool::new(false),
+            connection: Mutex::new(None),
+            artwork: Mutex::new(ArtworkTracker::default()),
+        };
+        (shared, receiver)
+    }
+
+    fn describe(event: SourceEvent) -> String {
+        match event {
+            SourceEvent::Message(SidecarMessage::ZoneList { zones }) => {
+                let zones: Vec<String> = zones.iter()
+                    .map(|zone| {
+                        let title = zone.now_playing.as_ref().map_or("-", |info| info.title.as_str());
+                        format!("{}={:?}:{}", zone.zone_id, zone.state, title)
+                    })
+                    .collect();
+                format!("zones [{}]", zones.join(", "))
+            }
+            SourceEvent::Message(SidecarMessage::NowPlaying { zone_id, title, state, seek_position, .. }) => {
+                format!("now_playing {} {} {:?} {:?}", zone_id, title, state, seek_position)
+            }
+            SourceEvent::Message(SidecarMessage::SeekChanged { zone_id, seek_position }) => {
+                format!("seek {} {}", zone_id, seek_position)
+            }
+            other => format!("{:?}", other),
+        }
+    }
+
+    fn drain(receiver: &Receiver<SourceEvent>) -> Vec<String> {
+        receiver.try_iter().map(describe).collect()
+    }
+
+    #[test]
+    fn polls_map_to_events() {
+        let (shared, receiver) = shared();
+        let mut reported = HashMap::new();
+        let bus = "org.mpris.MediaPlayer2.vlc";
+
+        // A new player with a track
+        let first = vec![snapshot(bus, PlaybackState::Playing, Some(track("One")), Some(10.0))];
+        shared.emit_changes(&first, &[], &mut reported);
+        assert_eq!(drain(&receiver), [
+            "zones [mpris:org.mpris.MediaPlayer2.vlc=Playing:One]",
+            "now_playing mpris:org.mpris.MediaPlayer2.vlc One Playing Some(10.0)",
+        ]);
+
+        // Nothing but the position changed, and by as much as expected
+        let steady = vec![snapshot(bus, PlaybackState::Playing, Some(track("One")), Some(10.2))];
+        shared.emit_changes(&steady, &first, &mut reported);
+        assert_eq!(drain(&receiver), Vec::<String>::new());
+
+        // A seek
+        let seeked = vec![snapshot(bus, PlaybackState::Playing, Some(track("One")), Some(95.0))];
+        shared.emit_changes(&seeked, &steady, &mut reported);
+        assert_eq!(drain(&receiver), ["seek mpris:org.mpris.MediaPlayer2.vlc 95"]);
+
+        // Pausing and changing track both resend the zone and now playing
+        let paused = vec![snapshot(bus, PlaybackState::Paused, Some(track("One")), Some(95.0))];
+        shared.emit_changes(&paused, &seeked, &mut reported);
+        assert_eq!(drain(&receiver), [
+            "zones [mpris:org.mpris.MediaPlayer2.vlc=Paused:One]",
+            "now_playing mpris:org.mpris.MediaPlayer2.vlc One Paused Some(95.0)",
+        ]);
+        let next = vec![snapshot(bus, PlaybackState::Playing, Some(track("Two")), Some(0.0))];
+        shared.emit_changes(&next, &paused, &mut reported);
+        assert_eq!(drain(&receiver), [
+            "zones [mpris:org.mpris.MediaPlayer2.vlc=Playing:Two]",
+            "now_playing mpris:org.mpris.MediaPlayer2.vlc Two Playing Some(0.0)",
+        ]);
+
+        // A second player with nothing loaded only shows up in the zone list
+        let idle = "org.mpris.MediaPlayer2.spotify";
+        let both = vec![
+            snapshot(idle, PlaybackState::Stopped, None, None),
+            snapshot(bus, PlaybackState::Playing, Some(track("Two")), Some(1.0)),
+        ];
+        shared.emit_changes(&both, &next, &mut reported);
+        assert_eq!(drain(&receiver), [
+            "zones [mpris:org.mpris.MediaPlayer2.spotify=Stopped:-, mpris:org.mpris.MediaPlayer2.vlc=Playing:Two]",
+        ]);
+
+        // Players that quit drop out of the zone list
+        shared.emit_changes(&[], &both, &mut reported);
+        assert_eq!(drain(&receiver), ["zones []"]);
+        assert!(reported.is_empty());
+    }
+
+    /// A scriptable MPRIS player for the session bus test
+    mod mock {
+        use parking_lot::Mutex;
+        use std::collections::HashMap;
+        use std::sync::Arc;
+        use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+        #[derive(Default)]
+        pub struct PlayerState {
+            pub status: String,
+            pub title: String,
+            pub position: i64,
+            pub calls: Vec<String>,
+        }
+
+        pub struct Root;
+
+        #[zbus::interface(name = "org.mpris.MediaPlayer2")]
+        impl Root {
+            #[zbus(property)]
+            fn identity(&self) -> String {
+                "Mock Player".to_string()
+            }
+        }
+
+        pub struct Player(pub Arc<Mutex<PlayerState>>);
+
+        #[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+        impl Player {
+            fn pause(&self) {
+                let mut state = self.0.lock();
+                state.calls.push("Pause".to_string());
+                state.status = "Paused".to_string();
+            }
+
+            fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+                let mut state = self.0.lock();
+                state.calls.push(format!("SetPosition {} {}", track_id, position));
+                state.position = position;
+            }
+
+            #[zbus(property)]
+            fn playback_status(&self) -> String {
+                self.0.lock().status.clone()
+            }
+
+            #[zbus(property)]
+            fn position(&self) -> i64 {
+                self.0.lock().position
+            }
+
+            #[zbus(property)]
+            fn metadata(&self) -> HashMap<String, OwnedValue> {
+                let title = self.0.lock().title.clone();
+                let entries: [(&str, Value<'_>); 4] = [
+                    ("mpris:trackid", ObjectPath::from_static_str_unchecked("/mock/track/1").into()),
+                    ("xesam:title", title.into()),
+                    ("xesam:artist", vec!["Mock Artist"].into()),
+                    ("mpris:length", 300_000_000i64.into()),
+                ];
+                entries.into_iter()
+                    .map(|(key, value)| (key.to_string(), OwnedValue::try_from(value).unwrap()))
+                    .collect()
+            }
+        }
+    }
+
+    /// Wait for the next event the test cares about, skipping the rest
+    fn wait_for(receiver: &Receiver<SourceEvent>, prefix: &str) -> String {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let event = receiver.recv_timeout(remaining)
+                .unwrap_or_else(|_| panic!("no {:?} event from the MPRIS source", prefix));
+            let event = describe(event);
+            if event.starts_with(prefix) {
+                return event;
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "starts a private dbus-daemon"]
+    fn follows_a_player_on_the_session_bus() {
+        use std::io::{BufRead, BufReader};
+        use std::process::{Command, Stdio};
+
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon should be installed");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+        // Only this test talks to the session bus, so pointing the process at it is safe
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", address.trim());
+
+        let state = Arc::new(Mutex::new(mock::PlayerState {
+            status: "Playing".to_string(),
+            title: "First".to_string(),
+            position: 30_000_000,
+            calls: Vec::new(),
+        }));
+        let player = zbus::blocking::connection::Builder::session()
+            .unwrap()
+            .name("org.mpris.MediaPlayer2.mock")
+            .unwrap()
+            .serve_at(MPRIS_PATH, mock::Root)
+            .unwrap()
+            .serve_at(MPRIS_PATH, mock::Player(state.clone()))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let source = MprisSource::new();
+        let (sender, receiver) = mpsc::channel();
+        source.start(sender).unwrap();
+        let zone = "mpris:org.mpris.MediaPlayer2.mock";
+
+        assert_eq!(wait_for(&receiver, "Status"), "Status(Connected)");
+        assert_eq!(wait_for(&receiver, "zones"), format!("zones [{}=Playing:First]", zone));
+        assert_eq!(wait_for(&receiver, "now_playing"), format!("now_playing {} First Playing Some(30.0)", zone));
+
+        state.lock().title = "Second".to_string();
+        assert_eq!(wait_for(&receiver, "now_playing"), format!("now_playing {} Second Playing Some(30.0)", zone));
+
+        source.send_command(SidecarCommand::Pause { zone_id: zone.to_string() }).unwrap();
+        assert_eq!(wait_for(&receiver, "now_playing"), format!("now_playing {} Second Paused Some(30.0)", zone));
+
+        source.send_command(SidecarCommand::Seek { zone_id: zone.to_string(), seconds: 120 }).unwrap();
+        assert_eq!(wait_for(&receiver, "seek"), format!("seek {} 120", zone));
+        assert_eq!(state.lock().calls, ["Pause", "SetPosition /mock/track/1 120000000"]);
+
+        drop(player);
+        assert_eq!(wait_for(&receiver, "zones"), "zones []");
+
+        source.stop().unwrap();
+        let _ = daemon.kill();
+        let _ = daemon.wait();
+    }
+}
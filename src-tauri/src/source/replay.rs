@@ -0,0 +1,230 @@
+//! Recording and replay of sidecar message streams
+//!
+//! Set MACAROON_RECORD to a file path and every message the sidecar sends is
+//! appended to it as JSON lines, each with its time since recording started.
+//! `MACAROON_SOURCE=replay` plays such a file back through the normal event
+//! path, so tray and zone-switching bugs can be reproduced without a Roon Core.
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::sidecar::into_event;
+use super::{sleep_unless, EventSender, NowPlayingSource};
+use crate::types::{SidecarCommand, SidecarMessage};
+
+/// One line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedMessage {
+    /// Milliseconds since the recording started
+    at_ms: u64,
+    message: SidecarMessage,
+}
+
+/// Appends received sidecar messages to a JSONL file
+pub struct Recorder {
+    writer: Mutex<BufWriter<File>>,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Create a recorder if MACAROON_RECORD is set
+    pub fn from_env() -> Option<Arc<Self>> {
+        let path = PathBuf::from(std::env::var_os("MACAROON_RECORD")?);
+        match Self::create(&path) {
+            Ok(recorder) => {
+                log::info!("Recording sidecar messages to {:?}", path);
+                Some(Arc::new(recorder))
+            }
+            Err(e) => {
+                log::error!("Failed to start recording: {}", e);
+                None
+            }
+        }
+    }
+
+    fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create recording {:?}", path))?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+            started: Instant::now(),
+        })
+    }
+
+    /// Append a message, flushing so the recording survives a crash
+    pub fn record(&self, message: &SidecarMessage) {
+        let entry = RecordedMessage {
+            at_ms: self.started.elapsed().as_millis() as u64,
+            message: message.clone(),
+        };
+
+        let result = serde_json::to_string(&entry)
+            .map_err(std::io::Error::from)
+            .and_then(|line| {
+                let mut writer = self.writer.lock();
+                writer.write_all(line.as_bytes())?;
+                writer.write_all(b"\n")?;
+                writer.flush()
+            });
+
+        if let Err(e) = result {
+            log::warn!("Failed to record sidecar message: {}", e);
+        }
+    }
+}
+
+/// Source that plays back a recording
+pub struct ReplaySource {
+    path: PathBuf,
+    /// Playback speed multiplier - 2.0 plays twice as fast
+    speed: f64,
+    shutdown_flag: Arc<AtomicBool>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ReplaySource {
+    pub fn new(path: PathBuf, speed: f64) -> Self {
+        Self {
+            path,
+            speed,
+            shutdown_flag: Arc::new(AtomicBool::new(false)),
+            worker: Mutex::new(None),
+        }
+    }
+
+    /// Read MACAROON_REPLAY (recording path) and MACAROON_REPLAY_SPEED (default 1.0)
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var_os("MACAROON_REPLAY")
+            .map(PathBuf::from)
+            .context("MACAROON_REPLAY must name the recording to play back")?;
+
+        let speed = match std::env::var("MACAROON_REPLAY_SPEED") {
+            Ok(value) => match value.parse::<f64>() {
+                Ok(speed) if speed > 0.0 && speed.is_finite() => speed,
+                _ => {
+                    log::warn!("Invalid MACAROON_REPLAY_SPEED '{}', playing at normal speed", value);
+                    1.0
+                }
+            },
+            Err(_) => 1.0,
+        };
+
+        Ok(Self::new(path, speed))
+    }
+
+    /// Play the recording, pacing messages by their timestamps
+    fn play(reader: BufReader<File>, speed: f64, events: EventSender, shutdown_flag: &AtomicBool) {
+        let started = Instant::now();
+        let mut played = 0;
+
+        for (index, line) in reader.lines().enumerate() {
+            if shutdown_flag.load(Ordering::SeqCst) {
+                return;
+            }
+
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+                Err(e) => {
+                    log::error!("Failed to read recording: {}", e);
+                    break;
+                }
+            };
+
+            let entry = match serde_json::from_str::<RecordedMessage>(&line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::warn!("Skipping invalid recording line {}: {}", index + 1, e);
+                    continue;
+                }
+            };
+
+            let due = Duration::from_secs_f64(entry.at_ms as f64 / 1000.0 / speed);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                if !sleep_unless(shutdown_flag, wait) {
+                    return;
+                }
+            }
+
+            // Handshakes and command replies only mattered to the live sidecar
+            if matches!(
+                entry.message,
+                SidecarMessage::Hello { .. } | SidecarMessage::Ack { .. } | SidecarMessage::CommandError { .. }
+            ) {
+                continue;
+            }
+
+            if events.send(into_event(entry.message)).is_err() {
+                log::debug!("Event receiver closed, stopping replay");
+                return;
+            }
+            played += 1;
+        }
+
+        log::info!("Replay finished after {} messages", played);
+    }
+}
+
+impl NowPlayingSource for ReplaySource {
+    fn name(&self) -> &'static str {
+        "Replay"
+    }
+
+    fn start(&self, events: EventSender) -> Result<()> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open recording {:?}", self.path))?;
+        log::info!("Replaying {:?} at {}x speed", self.path, self.speed);
+
+        self.shutdown_flag.store(false, Ordering::SeqCst);
+        let shutdown_flag = self.shutdown_flag.clone();
+        let speed = self.speed;
+        let handle = thread::Builder::new()
+            .name("replay".to_string())
+            .spawn(move || Self::play(BufReader::new(file), speed, events, &shutdown_flag))
+            .context("Failed to spawn replay thread")?;
+        *self.worker.lock() = Some(handle);
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        self.shutdown_flag.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.worker.lock().take() {
+            if handle.join().is_err() {
+                log::warn!("Failed to join replay thread");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_command(&self, command: SidecarCommand) -> Result<()> {
+        log::info!("Replay ignores {} command", command.name());
+        Ok(())
+    }
+
+    fn has_capability(&self, _capability: &str) -> bool {
+        // A recording can't be controlled
+        false
+    }
+
+    fn speed(&self) -> f64 {
+        self.speed
+    }
+}
+
+impl Drop for ReplaySource {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            log::error!("Error stopping replay in Drop: {}", e);
+        }
+    }
+}
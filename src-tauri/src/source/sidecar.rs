@@ -0,0 +1,708 @@
+use anyhow::{Context, Result};
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::replay::Recorder;
+use super::{EventSender, NowPlayingSource, SourceEvent};
+use crate::artwork;
+use crate::types::{ConnectionStatus, SidecarCommand, SidecarMessage, SidecarRequest};
+
+/// IPC protocol version this app speaks. Bumped only for breaking changes -
+/// additive features are negotiated through capabilities instead
+/// v2: artwork moved out of now_playing into separate artwork messages
+const PROTOCOL_VERSION: u32 = 2;
+
+/// Maximum size for a single IPC message line
+/// Artwork messages are the largest, so this is sized to fit any image the
+/// artwork cache accepts
+const MAX_MESSAGE_SIZE: usize = artwork::max_artwork_message_size();
+
+/// Zone ID the sidecar uses in `now_playing` to signal it lost the Roon Core
+const DISCONNECTED_ZONE_ID: &str = "__disconnected__";
+
+/// Configuration for sidecar restart backoff
+const RESTART_INITIAL_DELAY_MS: u64 = 1000;
+const RESTART_MAX_DELAY_MS: u64 = 30000;
+const RESTART_MULTIPLIER: u64 = 2;
+
+/// How long a command may wait for its reply before it is given up on
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A command written to the sidecar that has not been acknowledged yet
+struct PendingCommand {
+    command: SidecarCommand,
+    sent_at: Instant,
+}
+
+/// Commands awaiting a reply, keyed by request ID
+type PendingCommands = Arc<Mutex<HashMap<u64, PendingCommand>>>;
+
+/// Convert a sidecar message into a source event, translating Roon-specific signals
+pub(super) fn into_event(message: SidecarMessage) -> SourceEvent {
+    match message {
+        SidecarMessage::NowPlaying { ref zone_id, .. } if zone_id == DISCONNECTED_ZONE_ID => {
+            SourceEvent::Disconnected
+        }
+        message => SourceEvent::Message(message),
+    }
+}
+
+/// Shared handles to the sidecar process - does NOT have Drop impl
+/// Cloned into reader and restart threads to avoid deadlock when they exit
+#[derive(Clone)]
+struct SidecarProcess {
+    /// Tauri resource directory, used to locate the bundled binary in production
+    resource_dir: Option<PathBuf>,
+    /// Where events are delivered, set by `start`
+    events: Arc<Mutex<Option<EventSender>>>,
+    /// Copies every received message to a file when recording is enabled
+    recorder: Option<Arc<Recorder>>,
+    child: Arc<Mutex<Option<Child>>>,
+    /// Command channel to the sidecar. Also kept alive so the sidecar does not
+    /// detect parent death
+    stdin_handle: Arc<Mutex<Option<std::process::ChildStdin>>>,
+    reader_handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    shutdown_flag: Arc<AtomicBool>,
+    restart_count: Arc<Mutex<u32>>,
+    next_request_id: Arc<AtomicU64>,
+    pending_commands: PendingCommands,
+    /// Capabilities advertised in the sidecar's handshake
+    capabilities: Arc<RwLock<Vec<String>>>,
+}
+
+impl SidecarProcess {
+    /// Deliver an event to the app
+    fn emit(&self, event: SourceEvent) {
+        if let Some(events) = self.events.lock().as_ref() {
+            if events.send(event).is_err() {
+                log::debug!("Event receiver closed, dropping sidecar event");
+            }
+        }
+    }
+
+    /// Kill the running sidecar and make sure it is not restarted
+    /// Used when the sidecar fails the protocol handshake - restarting the same binary won't help
+    fn refuse(&self) {
+        self.shutdown_flag.store(true, Ordering::SeqCst);
+        if let Some(mut child) = self.child.lock().take() {
+            log::info!("Terminating incompatible sidecar process with PID {}", child.id());
+            if let Err(e) = child.kill() {
+                log::warn!("Failed to kill incompatible sidecar: {}", e);
+            }
+            let _ = child.wait();
+        }
+    }
+
+    /// Reset the restart counter (call after successful connection)
+    fn reset_restart_count(&self) {
+        *self.restart_count.lock() = 0;
+    }
+
+    /// Get the current restart delay based on exponential backoff
+    fn get_restart_delay(&self) -> Duration {
+        let count = *self.restart_count.lock();
+        let delay_ms = RESTART_INITIAL_DELAY_MS * RESTART_MULTIPLIER.saturating_pow(count);
+        Duration::from_millis(delay_ms.min(RESTART_MAX_DELAY_MS))
+    }
+
+    /// Increment restart count and return the new value
+    fn increment_restart_count(&self) -> u32 {
+        let mut count = self.restart_count.lock();
+        *count = count.saturating_add(1);
+        *count
+    }
+
+    /// Check whether the running sidecar advertised a capability
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.read().iter().any(|c| c == capability)
+    }
+
+    /// Spawn the sidecar process and start reading its output
+    fn spawn(&self) -> Result<()> {
+        log::info!("Spawning sidecar process...");
+
+        // Spawn the process based on environment
+        let mut child = if cfg!(debug_assertions) {
+            // Development mode: run with node directly
+            // In dev mode, current_dir is the project root (where we run npm run tauri dev)
+            let mut script_path = std::env::current_dir()
+                .context("Failed to get current directory")?
+                .join("sidecar/build/index.js");
+
+            // If that doesn't exist, try going up one level (in case we're in src-tauri)
+            if !script_path.exists() {
+                script_path = std::env::current_dir()
+                    .context("Failed to get current directory")?
+                    .parent()
+                    .context("No parent directory")?
+                    .join("sidecar/build/index.js");
+            }
+
+            if !script_path.exists() {
+                anyhow::bail!(
+                    "Sidecar script not found at {:?}. Run 'cd sidecar && npm run build' first.",
+                    script_path
+                );
+            }
+
+            log::info!("Running sidecar in development mode: node {:?}", script_path);
+
+            // Check for ROON_HOST environment variable for manual connection
+            let mut cmd = Command::new("node");
+            cmd.arg(&script_path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+
+            // Pass through ROON_HOST and ROON_PORT if set
+            if let Ok(host) = std::env::var("ROON_HOST") {
+                log::info!("Using manual Roon Core address: {}", host);
+                cmd.env("ROON_HOST", host);
+            }
+            if let Ok(port) = std::env::var("ROON_PORT") {
+                cmd.env("ROON_PORT", port);
+            }
+
+            cmd.spawn()
+                .context("Failed to spawn sidecar with node")?
+        } else {
+            // Production mode: use bundled binary
+            log::info!("Running sidecar in production mode");
+
+            // Resolve the sidecar binary path using Tauri's resource API
+            let resource_path = self.resource_dir.as_ref()
+                .context("Failed to get resource directory")?
+                .join("../MacOS/roon-sidecar");
+
+            let sidecar_path = resource_path.to_str()
+                .context("Failed to convert sidecar path to string")?;
+
+            log::info!("Sidecar path: {}", sidecar_path);
+
+            // Check if sidecar exists
+            if !resource_path.exists() {
+                anyhow::bail!("Sidecar binary not found at {:?}", resource_path);
+            }
+
+            let mut cmd = Command::new(sidecar_path);
+            cmd.stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+
+            // Pass through ROON_HOST and ROON_PORT if set
+            if let Ok(host) = std::env::var("ROON_HOST") {
+                log::info!("Using manual Roon Core address: {}", host);
+                cmd.env("ROON_HOST", host);
+            }
+            if let Ok(port) = std::env::var("ROON_PORT") {
+                cmd.env("ROON_PORT", port);
+            }
+
+            cmd.spawn()
+                .context("Failed to spawn sidecar process")?
+        };
+
+        log::info!("Sidecar process spawned with PID: {}", child.id());
+
+        // Get stdin, stdout and stderr
+        let stdin = child
+            .stdin
+            .take()
+            .context("Failed to capture sidecar stdin")?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .context("Failed to capture sidecar stdout")?;
+
+        let stderr = child
+            .stderr
+            .take()
+            .context("Failed to capture sidecar stderr")?;
+
+        // Store the child process and stdin handle
+        // Keeping stdin handle alive prevents the sidecar from detecting parent death
+        *self.child.lock() = Some(child);
+        *self.stdin_handle.lock() = Some(stdin);
+
+        // Reset shutdown flag for new spawn
+        self.shutdown_flag.store(false, Ordering::SeqCst);
+
+        // Replies to commands sent to a previous process will never arrive
+        self.pending_commands.lock().clear();
+
+        // Capabilities are re-advertised by the new process
+        self.capabilities.write().clear();
+
+        // Spawn thread to read stdout (JSON messages)
+        let process = self.clone();
+        let stdout_handle = thread::spawn(move || {
+            process.read_stdout(stdout);
+        });
+
+        // Spawn thread to read stderr (debug logs)
+        let shutdown_flag_stderr = self.shutdown_flag.clone();
+        let stderr_handle = thread::spawn(move || {
+            Self::read_stderr(stderr, shutdown_flag_stderr);
+        });
+
+        // Store handles for joining later
+        {
+            let mut handles = self.reader_handles.lock();
+            handles.push(stdout_handle);
+            handles.push(stderr_handle);
+        }
+
+        Ok(())
+    }
+
+    /// Send a transport command to the sidecar
+    /// Returns the request ID; the outcome arrives later as an `Ack` or `CommandError`
+    fn send_command(&self, command: SidecarCommand) -> Result<u64> {
+        if !self.has_capability(command.required_capability()) {
+            anyhow::bail!("Sidecar does not support the '{}' command", command.name());
+        }
+
+        let id = self.next_request_id.fetch_add(1, Ordering::SeqCst);
+        let name = command.name();
+        let request = SidecarRequest { id, command };
+
+        let mut line = serde_json::to_string(&request)
+            .context("Failed to serialize sidecar command")?;
+        line.push('\n');
+
+        // Register before writing so a fast reply can't race the insert
+        {
+            let mut pending_commands = self.pending_commands.lock();
+            // Drop commands whose reply never came, or they stay until the next restart
+            pending_commands.retain(|pending_id, pending| {
+                let waited = pending.sent_at.elapsed();
+                if waited < COMMAND_TIMEOUT {
+                    return true;
+                }
+                log::warn!(
+                    "Command #{} ({}) timed out after {:?}",
+                    pending_id,
+                    pending.command.name(),
+                    waited
+                );
+                false
+            });
+            pending_commands.insert(id, PendingCommand {
+                command: request.command,
+                sent_at: Instant::now(),
+            });
+        }
+
+        let write_result = {
+            let mut stdin_guard = self.stdin_handle.lock();
+            match stdin_guard.as_mut() {
+                Some(stdin) => stdin
+                    .write_all(line.as_bytes())
+                    .and_then(|_| stdin.flush())
+                    .context("Failed to write command to sidecar"),
+                None => Err(anyhow::anyhow!("Sidecar is not running")),
+            }
+        };
+
+        if let Err(e) = write_result {
+            self.pending_commands.lock().remove(&id);
+            return Err(e);
+        }
+
+        log::info!("Sent command #{} ({}) to sidecar", id, name);
+        Ok(id)
+    }
+
+    /// Match a command reply against the pending request it answers
+    fn resolve_command(&self, id: u64, error: Option<String>) {
+        let Some(pending) = self.pending_commands.lock().remove(&id) else {
+            log::warn!("Received reply for unknown command #{}", id);
+            return;
+        };
+
+        match error {
+            None => log::info!(
+                "Command #{} ({}) acknowledged after {:?}",
+                id,
+                pending.command.name(),
+                pending.sent_at.elapsed()
+            ),
+            Some(message) => log::error!(
+                "Command #{} ({}) failed: {}",
+                id,
+                pending.command.name(),
+                message
+            ),
+        }
+    }
+
+    /// Validate the first message from a sidecar process
+    /// Returns the advertised capabilities, or the reason the sidecar is incompatible
+    fn check_handshake(message: &SidecarMessage) -> std::result::Result<Vec<String>, String> {
+        match message {
+            SidecarMessage::Hello { protocol_version, capabilities } => {
+                if *protocol_version == PROTOCOL_VERSION {
+                    Ok(capabilities.clone())
+                } else {
+                    Err(format!(
+                        "sidecar speaks protocol v{}, app requires v{}",
+                        protocol_version, PROTOCOL_VERSION
+                    ))
+                }
+            }
+            _ => Err("sidecar did not send a protocol handshake".to_string()),
+        }
+    }
+
+    /// Read stdout from the sidecar (JSON messages)
+    /// Runs on a clone of the process handles, not SidecarManager, to avoid deadlock on drop
+    fn read_stdout(self, stdout: std::process::ChildStdout) {
+        let shutdown_flag = self.shutdown_flag.clone();
+        let reader = BufReader::new(stdout);
+        let mut handshake_complete = false;
+
+        for line in reader.lines() {
+            // Check if we should stop
+            if shutdown_flag.load(Ordering::SeqCst) {
+                log::debug!("Sidecar stdout reader received shutdown signal");
+                break;
+            }
+
+            match line {
+                Ok(line) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    // Validate message size to prevent OOM attacks
+                    if line.len() > MAX_MESSAGE_SIZE {
+                        log::error!(
+                            "Sidecar message exceeds size limit ({} > {}), discarding",
+                            line.len(), MAX_MESSAGE_SIZE
+                        );
+                        continue;
+                    }
+
+                    log::debug!("Sidecar stdout: {}", &line[..line.len().min(200)]);
+
+                    // Parse JSON message
+                    let message = match serde_json::from_str::<SidecarMessage>(&line) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            log::error!(
+                                "Failed to parse sidecar message: {} - {}",
+                                e, &line[..line.len().min(200)]
+                            );
+                            // Counted like a message type this app doesn't know
+                            self.emit(SourceEvent::Message(SidecarMessage::Unknown));
+                            continue;
+                        }
+                    };
+
+                    if let Some(recorder) = &self.recorder {
+                        recorder.record(&message);
+                    }
+
+                    // The first message must be the protocol handshake
+                    if !handshake_complete {
+                        match Self::check_handshake(&message) {
+                            Ok(advertised) => {
+                                log::info!(
+                                    "Sidecar handshake complete (protocol v{}, capabilities: {:?})",
+                                    PROTOCOL_VERSION, advertised
+                                );
+                                *self.capabilities.write() = advertised;
+                                handshake_complete = true;
+
+                                // Capability-gated menu items can appear now
+                                self.emit(SourceEvent::CapabilitiesChanged);
+                                continue;
+                            }
+                            Err(reason) => {
+                                log::error!("Refusing incompatible sidecar: {}", reason);
+                                self.emit(SourceEvent::Status(ConnectionStatus::Incompatible(reason)));
+                                self.refuse();
+                                break;
+                            }
+                        }
+                    }
+
+                    match message {
+                        // Command replies only concern the pending request table
+                        SidecarMessage::Ack { id } => {
+                            self.resolve_command(id, None);
+                        }
+                        SidecarMessage::CommandError { id, message } => {
+                            self.resolve_command(id, Some(message));
+                        }
+                        message => {
+                            // Reset restart count on successful connection
+                            if matches!(message, SidecarMessage::Status { ref state, .. } if state == "connected") {
+                                self.reset_restart_count();
+                            }
+
+                            self.emit(into_event(message));
+                        }
+                    }
+                }
+                Err(e) => {
+                    if !shutdown_flag.load(Ordering::SeqCst) {
+                        log::error!("Error reading sidecar stdout: {}", e);
+                    }
+                    break;
+                }
+            }
+        }
+
+        // Check if we should attempt restart
+        if !shutdown_flag.load(Ordering::SeqCst) {
+            log::warn!("Sidecar stdout reader stopped unexpectedly, scheduling restart...");
+
+            // Update connection status to show disconnection
+            self.emit(SourceEvent::Status(ConnectionStatus::Error("Sidecar process exited".to_string())));
+
+            // Schedule restart with backoff in a new thread
+            // The thread owns a process clone (not SidecarManager) to avoid deadlock when it exits
+            let process = self.clone();
+
+            thread::spawn(move || {
+                let restart_count = process.increment_restart_count();
+                let delay = process.get_restart_delay();
+                log::info!("Sidecar restart #{} scheduled in {:?}", restart_count, delay);
+
+                // Sleep for backoff delay
+                thread::sleep(delay);
+
+                // Check if we should still restart
+                if !process.shutdown_flag.load(Ordering::SeqCst) {
+                    log::info!("Attempting to restart sidecar...");
+                    if let Err(e) = process.spawn() {
+                        log::error!("Failed to restart sidecar: {}", e);
+                        // Update status to show error
+                        process.emit(SourceEvent::Status(ConnectionStatus::Error(format!("Restart failed: {}", e))));
+                    } else {
+                        log::info!("Sidecar restarted successfully");
+                    }
+                }
+            });
+        } else {
+            log::debug!("Sidecar stdout reader stopped (shutdown)");
+        }
+    }
+
+    /// Read stderr from the sidecar (debug logs)
+    fn read_stderr(stderr: std::process::ChildStderr, shutdown_flag: Arc<AtomicBool>) {
+        let reader = BufReader::new(stderr);
+
+        for line in reader.lines() {
+            // Check if we should stop
+            if shutdown_flag.load(Ordering::SeqCst) {
+                log::debug!("Sidecar stderr reader received shutdown signal");
+                break;
+            }
+
+            match line {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        log::info!("[Sidecar] {}", line);
+                    }
+                }
+                Err(e) => {
+                    if !shutdown_flag.load(Ordering::SeqCst) {
+                        log::error!("Error reading sidecar stderr: {}", e);
+                    }
+                    break;
+                }
+            }
+        }
+
+        if !shutdown_flag.load(Ordering::SeqCst) {
+            log::warn!("Sidecar stderr reader stopped unexpectedly");
+        } else {
+            log::debug!("Sidecar stderr reader stopped (shutdown)");
+        }
+    }
+
+    /// Check if the sidecar is still running
+    fn is_running(&self) -> bool {
+        let mut child_guard = self.child.lock();
+        if let Some(child) = child_guard.as_mut() {
+            match child.try_wait() {
+                Ok(Some(_status)) => {
+                    log::warn!("Sidecar process has exited");
+                    false
+                }
+                Ok(None) => true,
+                Err(e) => {
+                    log::error!("Error checking sidecar status: {}", e);
+                    false
+                }
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Stop the sidecar process
+    fn stop(&self) -> Result<()> {
+        // Signal reader threads to stop
+        self.shutdown_flag.store(true, Ordering::SeqCst);
+
+        // Drop stdin handle first - this signals the sidecar that parent is closing
+        // and allows it to shut down gracefully before we send SIGTERM
+        let _ = self.stdin_handle.lock().take();
+
+        let child_option = self.child.lock().take();
+        if let Some(mut child) = child_option {
+            log::info!("Stopping sidecar process with PID {}...", child.id());
+
+            // Send SIGTERM for graceful shutdown
+            #[cfg(unix)]
+            {
+                use nix::sys::signal::{kill, Signal};
+                use nix::unistd::Pid;
+
+                let pid = Pid::from_raw(child.id() as i32);
+                log::info!("Sending SIGTERM to sidecar process {}", pid);
+
+                if let Err(e) = kill(pid, Signal::SIGTERM) {
+                    log::warn!("Failed to send SIGTERM: {}", e);
+                }
+            }
+
+            // On Windows, just try to kill it
+            #[cfg(windows)]
+            {
+                log::info!("Killing sidecar process (Windows)");
+                child.kill().ok();
+            }
+
+            // Wait for graceful shutdown with timeout
+            const GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+            const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+            let start = Instant::now();
+
+            while start.elapsed() < GRACEFUL_SHUTDOWN_TIMEOUT {
+                match child.try_wait() {
+                    Ok(Some(status)) => {
+                        log::info!("Sidecar process exited gracefully with status: {:?}", status);
+                        // Join reader threads (they should exit when pipes close)
+                        self.join_reader_threads();
+                        return Ok(());
+                    }
+                    Ok(None) => {
+                        thread::sleep(CHECK_INTERVAL);
+                    }
+                    Err(e) => {
+                        log::error!("Error checking sidecar status: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            // Process didn't exit gracefully, force kill
+            log::warn!("Sidecar didn't stop after {:?}, sending SIGKILL...", GRACEFUL_SHUTDOWN_TIMEOUT);
+            child.kill().context("Failed to kill sidecar process")?;
+            child.wait().context("Failed to wait for sidecar process")?;
+            log::info!("Sidecar process forcefully terminated");
+        }
+
+        // Join reader threads
+        self.join_reader_threads();
+
+        Ok(())
+    }
+
+    /// Join all reader threads, with a timeout
+    fn join_reader_threads(&self) {
+        let handles: Vec<JoinHandle<()>> = {
+            let mut handles_guard = self.reader_handles.lock();
+            std::mem::take(&mut *handles_guard)
+        };
+
+        for handle in handles {
+            // Give threads a short time to finish
+            // They should exit quickly once the process is dead
+            if handle.join().is_err() {
+                log::warn!("Failed to join a reader thread");
+            }
+        }
+    }
+}
+
+/// Manages the Node.js sidecar process - the Roon source
+pub struct SidecarManager {
+    process: SidecarProcess,
+}
+
+impl SidecarManager {
+    pub fn new(resource_dir: Option<PathBuf>) -> Self {
+        Self {
+            process: SidecarProcess {
+                resource_dir,
+                events: Arc::new(Mutex::new(None)),
+                recorder: None,
+                child: Arc::new(Mutex::new(None)),
+                stdin_handle: Arc::new(Mutex::new(None)),
+                reader_handles: Arc::new(Mutex::new(Vec::new())),
+                shutdown_flag: Arc::new(AtomicBool::new(false)),
+                restart_count: Arc::new(Mutex::new(0)),
+                next_request_id: Arc::new(AtomicU64::new(1)),
+                pending_commands: Arc::new(Mutex::new(HashMap::new())),
+                capabilities: Arc::new(RwLock::new(Vec::new())),
+            },
+        }
+    }
+
+    /// Record every message this sidecar sends (see `replay::Recorder`)
+    pub fn with_recorder(mut self, recorder: Option<Arc<Recorder>>) -> Self {
+        self.process.recorder = recorder;
+        self
+    }
+
+    /// Check if the sidecar is still running
+    #[allow(dead_code)]
+    pub fn is_running(&self) -> bool {
+        self.process.is_running()
+    }
+}
+
+impl NowPlayingSource for SidecarManager {
+    fn name(&self) -> &'static str {
+        "Roon"
+    }
+
+    fn start(&self, events: EventSender) -> Result<()> {
+        *self.process.events.lock() = Some(events);
+        self.process.spawn()
+    }
+
+    fn stop(&self) -> Result<()> {
+        self.process.stop()
+    }
+
+    fn send_command(&self, command: SidecarCommand) -> Result<()> {
+        self.process.send_command(command).map(|_| ())
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.process.has_capability(capability)
+    }
+}
+
+impl Drop for SidecarManager {
+    fn drop(&mut self) {
+        log::info!("SidecarManager Drop called, cleaning up...");
+        if let Err(e) = self.process.stop() {
+            log::error!("Error stopping sidecar in Drop: {}", e);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Display templates for the menu bar text
+//!
+//! `{field}` inserts a track field (see `Field`). `[...]` is a conditional
+//! section that is dropped when any field directly inside it is empty; nested
+//! sections collapse on their own. Inside a section, `|` separates
+//! alternatives and the first one whose fields are all present is used.
+//! A backslash escapes the next character, e.g. `\[`.
+
+use anyhow::{anyhow, bail, Result};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+
+use crate::credits::{self, CreditStyle};
+use crate::types::format_duration;
+
+/// "Title - Artist", or whichever of the two is present
+pub const DEFAULT_TEMPLATE: &str = "[{title} - {credits}|{title}|{credits}]";
+
+/// Track fields a template can refer to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Title,
+    Artist,
+    PrimaryArtist,
+    /// Artists in the configured credit style
+    Credits,
+    Album,
+    Zone,
+    Elapsed,
+    Length,
+}
+
+impl Field {
+    /// Fields that show the artist credit in some form
+    pub fn is_artist(self) -> bool {
+        matches!(self, Self::Artist | Self::PrimaryArtist | Self::Credits)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "title" => Some(Self::Title),
+            "artist" => Some(Self::Artist),
+            "primary_artist" => Some(Self::PrimaryArtist),
+            "credits" => Some(Self::Credits),
+            "album" => Some(Self::Album),
+            "zone" => Some(Self::Zone),
+            "elapsed" => Some(Self::Elapsed),
+            "length" => Some(Self::Length),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+    /// Alternatives, each a sequence of parts
+    Section(Vec<Vec<Part>>),
+}
+
+/// Values a template is rendered with
+#[derive(Debug, Default)]
+pub struct TemplateFields<'a> {
+    pub title: &'a str,
+    pub artist: &'a str,
+    pub album: &'a str,
+    pub zone: &'a str,
+    /// Playback position in seconds
+    pub elapsed: Option<f64>,
+    /// Track length in seconds
+    pub length: Option<f64>,
+    pub credit_style: CreditStyle,
+}
+
+impl TemplateFields<'_> {
+    fn value(&self, field: Field) -> String {
+        match field {
+            Field::Title => self.title.trim().to_string(),
+            Field::Artist => self.artist.trim().to_string(),
+            Field::PrimaryArtist => credits::primary_artist(self.artist).to_string(),
+            Field::Credits => credits::format_credits(self.artist, self.credit_style),
+            Field::Album => self.album.trim().to_string(),
+            Field::Zone => self.zone.trim().to_string(),
+            Field::Elapsed => self.elapsed.map(format_duration).unwrap_or_default(),
+            Field::Length => self.length.filter(|l| *l > 0.0).map(format_duration).unwrap_or_default(),
+        }
+    }
+}
+
+/// Template output, with where the artist credit ended up
+#[derive(Debug, Clone, Default)]
+pub struct RenderedText {
+    pub text: String,
+    /// Byte range spanning every artist field in `text`
+    pub artist: Option<Range<usize>>,
+}
+
+/// A parsed display template
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).expect("default template is valid")
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut chars = source.chars().peekable();
+        let parts = parse_sequence(&mut chars, false)?
+            .pop()
+            .unwrap_or_default();
+        Ok(Self { parts })
+    }
+
+    /// Render the template, trimming surrounding whitespace
+    pub fn render(&self, fields: &TemplateFields) -> RenderedText {
+        let mut output = String::new();
+        let mut spans = Vec::new();
+        render_parts(&self.parts, fields, &mut output, &mut spans);
+
+        let text = output.trim();
+        let leading = output.len() - output.trim_start().len();
+        let artist = spans.iter()
+            .filter(|(field, span)| field.is_artist() && !span.is_empty())
+            .map(|(_, span)| span.start - leading..span.end - leading)
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+
+        RenderedText { text: text.to_string(), artist }
+    }
+
+    /// Whether the template refers to a field anywhere
+    pub fn uses(&self, field: Field) -> bool {
+        fn search(parts: &[Part], field: Field) -> bool {
+            parts.iter().any(|part| match part {
+                Part::Text(_) => false,
+                Part::Field(f) => *f == field,
+                Part::Section(alternatives) => alternatives.iter().any(|alt| search(alt, field)),
+            })
+        }
+        search(&self.parts, field)
+    }
+}
+
+/// Parse until the end of input, or the closing `]` of a section
+/// Returns the alternatives; outside a section there is exactly one
+fn parse_sequence(chars: &mut Peekable<Chars>, in_section: bool) -> Result<Vec<Vec<Part>>> {
+    let mut alternatives = Vec::new();
+    let mut parts = Vec::new();
+    let mut text = String::new();
+
+    let flush = |text: &mut String, parts: &mut Vec<Part>| {
+        if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(text)));
+        }
+    };
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                let escaped = chars.next().ok_or_else(|| anyhow!("template ends with a lone '\\'"))?;
+                text.push(escaped);
+            }
+            '{' => {
+                flush(&mut text, &mut parts);
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => bail!("unclosed '{{' in template"),
+                    }
+                }
+                let field = Field::from_name(name.trim())
+                    .ok_or_else(|| anyhow!("unknown template field {{{}}}", name.trim()))?;
+                parts.push(Part::Field(field));
+            }
+            '}' => bail!("unmatched '}}' in template"),
+            '[' => {
+                flush(&mut text, &mut parts);
+                parts.push(Part::Section(parse_sequence(chars, true)?));
+            }
+            ']' if in_section => {
+                flush(&mut text, &mut parts);
+                alternatives.push(parts);
+                return Ok(alternatives);
+            }
+            ']' => bail!("unmatched ']' in template"),
+            '|' if in_section => {
+                flush(&mut text, &mut parts);
+                alternatives.push(std::mem::take(&mut parts));
+            }
+            _ => text.push(ch),
+        }
+    }
+
+    if in_section {
+        bail!("unclosed '[' in template");
+    }
+
+    flush(&mut text, &mut parts);
+    alternatives.push(parts);
+    Ok(alternatives)
+}
+
+/// Append rendered parts to `output`, recording where each field went in `spans`
+/// Returns false if a field directly in `parts` was empty
+fn render_parts(
+    parts: &[Part],
+    fields: &TemplateFields,
+    output: &mut String,
+    spans: &mut Vec<(Field, Range<usize>)>,
+) -> bool {
+    let mut complete = true;
+
+    for part in parts {
+        match part {
+            Part::Text(text) => output.push_str(text),
+            Part::Field(field) => {
+                let value = fields.value(*field);
+                complete &= !value.is_empty();
+                spans.push((*field, output.len()..output.len() + value.len()));
+                output.push_str(&value);
+            }
+            Part::Section(alternatives) => {
+                for alternative in alternatives {
+                    let mut rendered = String::new();
+                    let mut rendered_spans = Vec::new();
+                    if render_parts(alternative, fields, &mut rendered, &mut rendered_spans) {
+                        let offset = output.len();
+                        spans.extend(rendered_spans.into_iter()
+                            .map(|(field, span)| (field, span.start + offset..span.end + offset)));
+                        output.push_str(&rendered);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    complete
+}
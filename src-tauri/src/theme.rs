@@ -0,0 +1,190 @@
+//! Icon colours for light and dark menu bars
+//!
+//! Each appearance has its own palette. Colours left unset fall back to the
+//! native look: black on a light menu bar, white on a dark one, with the
+//! placeholder and accent following the text colour. In template mode the
+//! icon is drawn in black and macOS tints it like its own menu bar icons.
+
+use anyhow::{anyhow, Result};
+use image::Rgba;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Approximate menu bar backgrounds, used for contrast checks
+const LIGHT_MENU_BAR: Color = Color([236, 236, 236, 255]);
+const DARK_MENU_BAR: Color = Color([38, 38, 38, 255]);
+
+/// Lowest contrast a custom colour may have against its menu bar,
+/// the WCAG minimum for large text and graphics
+pub const MIN_CONTRAST: f32 = 3.0;
+
+/// An sRGB colour with alpha, written "#rrggbb" or "#rrggbbaa" in settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color(pub [u8; 4]);
+
+impl Color {
+    pub const BLACK: Color = Color([0, 0, 0, 255]);
+    pub const WHITE: Color = Color([255, 255, 255, 255]);
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let hex = text.trim().strip_prefix('#')
+            .ok_or_else(|| anyhow!("colour {:?} should start with #", text))?;
+        if !matches!(hex.len(), 6 | 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("colour {:?} should be #rrggbb or #rrggbbaa", text));
+        }
+
+        let channel = |i: usize| hex.get(i * 2..i * 2 + 2).map_or(Ok(255), |pair| u8::from_str_radix(pair, 16));
+        let parsed = [channel(0), channel(1), channel(2), channel(3)];
+        let mut rgba = [0; 4];
+        for (value, parsed) in rgba.iter_mut().zip(parsed) {
+            *value = parsed.map_err(|_| anyhow!("colour {:?} isn't valid hex", text))?;
+        }
+        Ok(Color(rgba))
+    }
+
+    pub fn rgba(self) -> Rgba<u8> {
+        Rgba(self.0)
+    }
+
+    /// This colour drawn over an opaque background
+    fn over(self, background: Color) -> Color {
+        let alpha = f32::from(self.0[3]) / 255.0;
+        let mut mixed = background.0;
+        for (channel, top) in mixed.iter_mut().zip(self.0).take(3) {
+            *channel = (f32::from(top) * alpha + f32::from(*channel) * (1.0 - alpha)).round() as u8;
+        }
+        Color(mixed)
+    }
+
+    /// WCAG relative luminance, ignoring alpha
+    fn luminance(self) -> f32 {
+        let linear = |channel: u8| {
+            let c = f32::from(channel) / 255.0;
+            if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        0.2126 * linear(self.0[0]) + 0.7152 * linear(self.0[1]) + 0.0722 * linear(self.0[2])
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [r, g, b, a] = self.0;
+        if a == 255 {
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Color::parse(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Contrast ratio of `foreground` drawn over `background`, from 1 (invisible)
+/// to 21 (black on white)
+pub fn contrast_ratio(foreground: Color, background: Color) -> f32 {
+    let background = Color([background.0[0], background.0[1], background.0[2], 255]);
+    let (a, b) = (foreground.over(background).luminance(), background.luminance());
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// The menu bar behind the icon, roughly
+pub fn menu_bar(dark: bool) -> Color {
+    if dark { DARK_MENU_BAR } else { LIGHT_MENU_BAR }
+}
+
+/// Black or white, whichever stands out more on `background`
+pub fn readable_on(background: Color) -> Color {
+    if contrast_ratio(Color::BLACK, background) >= contrast_ratio(Color::WHITE, background) {
+        Color::BLACK
+    } else {
+        Color::WHITE
+    }
+}
+
+/// Colours for one appearance; unset ones use the native look
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct Palette {
+    /// Track text, pause symbol and spinner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<Color>,
+    /// The macaroon shown when nothing is playing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<Color>,
+    /// The played part of the progress bar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accent: Option<Color>,
+}
+
+impl Palette {
+    /// Unset colours that are hard to read on the menu bar, describing each one
+    fn drop_unreadable(&mut self, appearance: &str, background: Color, problems: &mut Vec<String>) {
+        for (name, slot) in [("text", &mut self.text), ("placeholder", &mut self.placeholder), ("accent", &mut self.accent)] {
+            let Some(color) = *slot else {
+                continue;
+            };
+            let contrast = contrast_ratio(color, background);
+            if contrast < MIN_CONTRAST {
+                problems.push(format!(
+                    "{}.{}: {} is hard to read on a {} menu bar (contrast {:.1}:1, needs {}:1)",
+                    appearance, name, color, appearance, contrast, MIN_CONTRAST
+                ));
+                *slot = None;
+            }
+        }
+    }
+}
+
+/// Icon colours for both appearances
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct Theme {
+    pub light: Palette,
+    pub dark: Palette,
+    /// Draw a monochrome template image that macOS tints itself
+    pub template: bool,
+}
+
+/// Colours resolved for the current appearance
+#[derive(Debug, Clone, Copy)]
+pub struct Colors {
+    pub text: Rgba<u8>,
+    pub placeholder: Rgba<u8>,
+    pub accent: Rgba<u8>,
+}
+
+impl Theme {
+    pub fn colors(&self, dark: bool) -> Colors {
+        if self.template {
+            let black = Color::BLACK.rgba();
+            return Colors { text: black, placeholder: black, accent: black };
+        }
+
+        let (palette, native) = if dark { (&self.dark, Color::WHITE) } else { (&self.light, Color::BLACK) };
+        let text = palette.text.unwrap_or(native);
+        Colors {
+            text: text.rgba(),
+            placeholder: palette.placeholder.unwrap_or(text).rgba(),
+            accent: palette.accent.unwrap_or(text).rgba(),
+        }
+    }
+
+    /// Unset custom colours that don't stand out from their menu bar
+    /// Returns a description of each one, relative to the theme
+    pub fn drop_unreadable(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        self.light.drop_unreadable("light", LIGHT_MENU_BAR, &mut problems);
+        self.dark.drop_unreadable("dark", DARK_MENU_BAR, &mut problems);
+        problems
+    }
+}
@@ -0,0 +1,111 @@
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Runtime};
+
+use crate::compositor::ANIMATION_FRAME;
+use crate::handler;
+use crate::state::SharedState;
+use crate::template::{Field, Template};
+use crate::tray::TrayManager;
+use crate::types::{PlaybackState, ProgressBarStyle, TextOverflow};
+
+/// How often the progress bar and elapsed time are redrawn while playing
+pub const PROGRESS_TICK: Duration = Duration::from_secs(1);
+
+/// Check whether the icon shows something that changes with time
+pub fn needs_redraw(state: &SharedState) -> bool {
+    let state_guard = state.read();
+    let Some(track) = state_guard.current_track.as_ref().filter(|t| t.state == PlaybackState::Playing) else {
+        return false;
+    };
+
+    let display = &state_guard.display_settings;
+    let shows_progress = track.length.is_some() && display.progress_bar != ProgressBarStyle::Off;
+    let shows_elapsed = Template::parse(&display.template).is_ok_and(|t| t.uses(Field::Elapsed));
+
+    shows_progress || shows_elapsed
+}
+
+/// Spawn a background thread that periodically redraws the tray icon
+/// while the displayed track is playing, so the progress bar advances
+/// between sidecar updates, and lets the smart switching grace period
+/// expire. Runs for the lifetime of the app.
+pub fn spawn<R: Runtime>(app: AppHandle<R>, state: SharedState) {
+    let result = thread::Builder::new()
+        .name("icon-ticker".to_string())
+        .spawn(move || loop {
+            thread::sleep(PROGRESS_TICK);
+
+            let effects = handler::apply_tick(&state);
+            if !effects.rebuild_menu && !effects.update_icon && !needs_redraw(&state) {
+                continue;
+            }
+
+            let app_clone = app.clone();
+            let state_clone = state.clone();
+            if let Err(e) = app.run_on_main_thread(move || {
+                if effects.rebuild_menu {
+                    if let Err(e) = TrayManager::rebuild_menu(&app_clone, &state_clone) {
+                        log::error!("Failed to rebuild menu on tick: {}", e);
+                    }
+                }
+                if let Err(e) = TrayManager::update_icon(&app_clone, &state_clone) {
+                    log::error!("Failed to update icon on tick: {}", e);
+                }
+            }) {
+                log::error!("Failed to dispatch icon tick to main thread: {}", e);
+            }
+        });
+
+    if let Err(e) = result {
+        log::error!("Failed to spawn icon ticker thread: {}", e);
+    }
+}
+
+/// Check whether a marquee or the loading spinner should be advancing
+fn animation_running<R: Runtime>(app: &AppHandle<R>, state: &SharedState) -> bool {
+    {
+        let state_guard = state.read();
+        let scrolls = state_guard.display_settings.overflow == TextOverflow::Marquee;
+        let moves = state_guard.current_track.as_ref().is_some_and(|track| match track.state {
+            PlaybackState::Playing => scrolls,
+            PlaybackState::Loading => true,
+            _ => false,
+        });
+        if !moves {
+            return false;
+        }
+    }
+
+    TrayManager::is_animating(app)
+}
+
+/// Spawn a background thread that advances the marquee while long text
+/// is scrolling, and the spinner while the zone is loading. It idles (one
+/// cheap check per frame) otherwise, e.g. when playback is paused or the
+/// text fits.
+pub fn spawn_animation<R: Runtime>(app: AppHandle<R>, state: SharedState) {
+    let result = thread::Builder::new()
+        .name("animation-ticker".to_string())
+        .spawn(move || loop {
+            thread::sleep(ANIMATION_FRAME);
+
+            if !animation_running(&app, &state) {
+                continue;
+            }
+
+            let app_clone = app.clone();
+            let state_clone = state.clone();
+            if let Err(e) = app.run_on_main_thread(move || {
+                if let Err(e) = TrayManager::update_icon(&app_clone, &state_clone) {
+                    log::error!("Failed to update animation frame: {}", e);
+                }
+            }) {
+                log::error!("Failed to dispatch animation frame to main thread: {}", e);
+            }
+        });
+
+    if let Err(e) = result {
+        log::error!("Failed to spawn animation thread: {}", e);
+    }
+}
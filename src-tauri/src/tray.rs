@@ -1,41 +1,52 @@
 use anyhow::{Context, Result};
 use tauri::{
     image::Image,
-    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem},
+    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
     tray::TrayIconBuilder,
     AppHandle, Manager, Runtime,
 };
 
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::artwork::ArtworkCache;
 use crate::autostart;
+use crate::handler;
+use crate::settings;
 use crate::compositor::Compositor;
+use crate::source::SharedSource;
 use crate::state::SharedState;
-use crate::types::{ConnectionStatus, PlaybackState, ZonePreference};
+use crate::types::{
+    capability, format_duration, ConnectionStatus, DisplaySettings, PlaybackState, ProgressBarStyle,
+    SidecarCommand, ZonePreference,
+};
 
 /// TrayManager is stored as a singleton in Tauri's app state.
-/// It owns the Compositor which loads the font once at startup.
+/// It owns the Compositor, which keeps the loaded fonts between renders.
 pub struct TrayManager {
     compositor: Compositor,
+    /// Whether the tray icon is currently a template image that macOS tints
+    template: AtomicBool,
 }
 
 impl TrayManager {
     /// Create a new TrayManager. This should only be called once during app setup.
-    /// If the font fails to load, returns an error that should terminate the app.
-    pub fn new() -> Result<Self> {
-        let compositor = Compositor::new()
-            .context("Failed to initialize compositor - font may be missing")?;
-        Ok(Self { compositor })
+    pub fn new() -> Self {
+        Self { compositor: Compositor::new(), template: AtomicBool::new(false) }
     }
 
     /// Initialize the system tray and store TrayManager as app state
     pub fn setup<R: Runtime>(app: &AppHandle<R>, state: SharedState) -> Result<()> {
-        // Create the TrayManager singleton - exit if font loading fails
-        let manager = TrayManager::new()?;
+        // Create the TrayManager singleton
+        let manager = TrayManager::new();
 
         // Create initial menu
         let menu = Self::build_menu_internal(app, &state)?;
 
         // Create initial icon
-        let initial_icon = manager.create_placeholder_icon()?;
+        let display = state.read().display_settings.clone();
+        let initial_icon = manager.create_placeholder_icon(&display)?;
+        manager.template.store(display.theme.template, Ordering::Relaxed);
 
         // Clone state for menu event handler
         let state_for_menu = state.clone();
@@ -43,6 +54,7 @@ impl TrayManager {
         // Build tray icon
         let tray = TrayIconBuilder::new()
             .icon(initial_icon)
+            .icon_as_template(display.theme.template)
             .menu(&menu)
             .on_menu_event(move |app, event| {
                 Self::handle_menu_event(app, event, &state_for_menu);
@@ -66,16 +78,30 @@ impl TrayManager {
         let state_guard = state.read();
         let menu = Menu::new(app)?;
 
+        let source_name = app
+            .try_state::<SharedSource>()
+            .map(|source| source.name())
+            .unwrap_or("Roon");
+
         // Show connection status if not connected
         match &state_guard.connection_status {
             ConnectionStatus::Disconnected => {
-                let item = MenuItem::with_id(app, "status", "Disconnected from Roon", false, None::<&str>)?;
+                let label = format!("Disconnected from {}", source_name);
+                let item = MenuItem::with_id(app, "status", &label, false, None::<&str>)?;
                 menu.append(&item)?;
                 let separator = PredefinedMenuItem::separator(app)?;
                 menu.append(&separator)?;
             }
             ConnectionStatus::Discovering => {
-                let item = MenuItem::with_id(app, "status", "Searching for Roon Core...", false, None::<&str>)?;
+                let label = format!("Searching for {}...", source_name);
+                let item = MenuItem::with_id(app, "status", &label, false, None::<&str>)?;
+                menu.append(&item)?;
+                let separator = PredefinedMenuItem::separator(app)?;
+                menu.append(&separator)?;
+            }
+            ConnectionStatus::Incompatible(msg) => {
+                let label = format!("Incompatible sidecar: {}", msg);
+                let item = MenuItem::with_id(app, "status", &label, false, None::<&str>)?;
                 menu.append(&item)?;
                 let separator = PredefinedMenuItem::separator(app)?;
                 menu.append(&separator)?;
@@ -92,6 +118,46 @@ impl TrayManager {
             }
         }
 
+        // Now playing section: position of the displayed track (as of this menu build)
+        // and transport controls, which are only offered when the source supports them
+        let mut has_now_playing_section = false;
+        if let Some(position) = state_guard.current_track.as_ref().and_then(|t| t.position()) {
+            let label = match state_guard.current_track.as_ref().and_then(|t| t.length) {
+                Some(length) if length > 0.0 => {
+                    format!("{} / {}", format_duration(position), format_duration(length))
+                }
+                _ => format_duration(position),
+            };
+            let item = MenuItem::with_id(app, "position", &label, false, None::<&str>)?;
+            menu.append(&item)?;
+            has_now_playing_section = true;
+        }
+
+        let supports_transport = app
+            .try_state::<SharedSource>()
+            .map(|source| source.has_capability(capability::TRANSPORT_CONTROL))
+            .unwrap_or(false);
+        let active_zone_state = state_guard.active_zone_id.as_ref().and_then(|active_id| {
+            state_guard.all_zones.iter().find(|z| &z.zone_id == active_id).map(|z| z.state.clone())
+        });
+        if supports_transport && state_guard.connection_status == ConnectionStatus::Connected {
+            if let Some(zone_state) = active_zone_state {
+                let play_pause_label = if zone_state == PlaybackState::Playing { "Pause" } else { "Play" };
+                let play_pause = MenuItem::with_id(app, "transport_playpause", play_pause_label, true, None::<&str>)?;
+                menu.append(&play_pause)?;
+                let next = MenuItem::with_id(app, "transport_next", "Next Track", true, None::<&str>)?;
+                menu.append(&next)?;
+                let previous = MenuItem::with_id(app, "transport_previous", "Previous Track", true, None::<&str>)?;
+                menu.append(&previous)?;
+                has_now_playing_section = true;
+            }
+        }
+
+        if has_now_playing_section {
+            let separator = PredefinedMenuItem::separator(app)?;
+            menu.append(&separator)?;
+        }
+
         // Add zone items
         if state_guard.all_zones.is_empty() {
             let no_zones = MenuItem::with_id(app, "no_zones", "No zones available", false, None::<&str>)?;
@@ -108,9 +174,18 @@ impl TrayManager {
                     PlaybackState::Paused => "Paused",
                     PlaybackState::Stopped => "Stopped",
                     PlaybackState::Loading => "Loading",
+                    PlaybackState::Unknown => "Unknown",
                 };
 
-                let label = format!("{} ({})", zone.display_name, state_str);
+                // Smart switching is showing this zone while the selected one is idle
+                let is_followed = state_guard.is_smart_switched
+                    && state_guard.active_zone_id.as_deref() == Some(zone.zone_id.as_str());
+
+                let label = if is_followed {
+                    format!("{} ({}, following)", zone.display_name, state_str)
+                } else {
+                    format!("{} ({})", zone.display_name, state_str)
+                };
 
                 let item = CheckMenuItem::with_id(
                     app,
@@ -128,6 +203,34 @@ impl TrayManager {
         let separator = PredefinedMenuItem::separator(app)?;
         menu.append(&separator)?;
 
+        // Hint that the sidecar is newer than this app
+        if state_guard.skipped_messages > 0 {
+            let label = format!("Skipped {} unrecognized updates", state_guard.skipped_messages);
+            let item = MenuItem::with_id(app, "skipped_messages", &label, false, None::<&str>)?;
+            menu.append(&item)?;
+        }
+
+        // Values in settings.json that were ignored
+        for (index, problem) in state_guard.settings_problems.iter().enumerate() {
+            let id = format!("settings_problem_{}", index);
+            let label = format!("Invalid setting: {}", problem);
+            let item = MenuItem::with_id(app, &id, &label, false, None::<&str>)?;
+            menu.append(&item)?;
+        }
+
+        // Progress bar placement
+        let progress_menu = Submenu::new(app, "Progress Bar", true)?;
+        let current_style = state_guard.display_settings.progress_bar;
+        for (id, label, style) in [
+            ("progress_bar_off", "Off", ProgressBarStyle::Off),
+            ("progress_bar_artwork", "Under Artwork", ProgressBarStyle::UnderArtwork),
+            ("progress_bar_text", "Under Text", ProgressBarStyle::UnderText),
+        ] {
+            let item = CheckMenuItem::with_id(app, id, label, true, current_style == style, None::<&str>)?;
+            progress_menu.append(&item)?;
+        }
+        menu.append(&progress_menu)?;
+
         // Launch at Login checkbox
         let launch_at_login = CheckMenuItem::with_id(
             app,
@@ -172,7 +275,30 @@ impl TrayManager {
                     }
                 }
             }
-            "no_zones" | "status" => {
+            "progress_bar_off" | "progress_bar_artwork" | "progress_bar_text" => {
+                let style = match menu_id {
+                    "progress_bar_artwork" => ProgressBarStyle::UnderArtwork,
+                    "progress_bar_text" => ProgressBarStyle::UnderText,
+                    _ => ProgressBarStyle::Off,
+                };
+                state.write().display_settings.progress_bar = style;
+                settings::save_state(state);
+                log::info!("Progress bar set to {:?}", style);
+
+                if let Err(e) = Self::rebuild_menu(app, state) {
+                    log::error!("Failed to rebuild menu: {}", e);
+                }
+                if let Err(e) = Self::update_icon(app, state) {
+                    log::error!("Failed to update icon after progress bar change: {}", e);
+                }
+            }
+            "transport_playpause" | "transport_next" | "transport_previous" => {
+                Self::send_transport_command(app, state, menu_id);
+            }
+            "no_zones" | "status" | "skipped_messages" | "position" => {
+                // Disabled items, do nothing
+            }
+            id if id.starts_with("settings_problem_") => {
                 // Disabled items, do nothing
             }
             zone_id => {
@@ -181,39 +307,15 @@ impl TrayManager {
 
                 {
                     let mut state_guard = state.write();
-                    state_guard.zone_preference = ZonePreference::Selected {
+                    let behavior = state_guard.behavior_settings.clone();
+                    handler::select_zone(&mut state_guard, ZonePreference::Selected {
                         zone_id: zone_id.to_string(),
-                        smart_switching: true,
-                        grace_period_mins: 5,
-                    };
-
-                    // Reset smart-switch state since user explicitly selected a zone
-                    state_guard.is_smart_switched = false;
-                    state_guard.preferred_zone_stopped_at = None;
-
-                    // Load the selected zone's now_playing data
-                    let zone_data = state_guard.all_zones.iter()
-                        .find(|z| z.zone_id == zone_id)
-                        .map(|z| (z.now_playing.clone(), z.display_name.clone()));
-
-                    if let Some((now_playing, display_name)) = zone_data {
-                        // Only update current_track if the zone has data
-                        // Keep existing track if zone is playing but data hasn't arrived yet
-                        if now_playing.is_some() {
-                            state_guard.current_track = now_playing;
-                        }
-                        // Always update active zone ID - the track data will arrive shortly
-                        state_guard.active_zone_id = Some(zone_id.to_string());
-                        log::info!("Selected zone: {}", display_name);
-                    } else {
-                        // Zone doesn't exist in our list - this shouldn't normally happen
-                        // Keep existing track to avoid flicker
-                        state_guard.active_zone_id = Some(zone_id.to_string());
-                        log::warn!("Selected zone not found in zone list: {}", zone_id);
-                    }
-
+                        smart_switching: behavior.smart_switching,
+                        grace_period_mins: behavior.grace_period_mins,
+                    });
                     state_guard.last_menu_rebuild = Some(std::time::Instant::now());
                 }
+                settings::save_state(state);
 
                 // Rebuild menu and update icon
                 if let Err(e) = Self::rebuild_menu(app, state) {
@@ -226,6 +328,30 @@ impl TrayManager {
         }
     }
 
+    /// Send a transport command for the active zone to the source
+    fn send_transport_command<R: Runtime>(app: &AppHandle<R>, state: &SharedState, menu_id: &str) {
+        let Some(zone_id) = state.read().active_zone_id.clone() else {
+            log::warn!("Transport command '{}' ignored: no active zone", menu_id);
+            return;
+        };
+
+        let command = match menu_id {
+            "transport_playpause" => SidecarCommand::PlayPause { zone_id },
+            "transport_next" => SidecarCommand::Next { zone_id },
+            "transport_previous" => SidecarCommand::Previous { zone_id },
+            _ => return,
+        };
+
+        match app.try_state::<SharedSource>() {
+            Some(source) => {
+                if let Err(e) = source.send_command(command) {
+                    log::error!("Failed to send transport command: {}", e);
+                }
+            }
+            None => log::error!("Source not found in app state, cannot send command"),
+        }
+    }
+
     /// Rebuild the tray menu (called when zones change or preference changes)
     pub fn rebuild_menu<R: Runtime>(app: &AppHandle<R>, state: &SharedState) -> Result<()> {
         let new_menu = Self::build_menu_internal(app, state)?;
@@ -238,8 +364,8 @@ impl TrayManager {
     }
 
     /// Create a placeholder icon (no track playing)
-    fn create_placeholder_icon(&self) -> Result<Image<'static>> {
-        let icon_bytes = self.compositor.create_menu_bar_icon(None, "", "")?;
+    fn create_placeholder_icon(&self, display: &DisplaySettings) -> Result<Image<'static>> {
+        let icon_bytes = self.compositor.create_menu_bar_icon(None, "", None, None, false, display)?;
         Image::from_bytes(&icon_bytes).context("Failed to create placeholder icon")
     }
 
@@ -255,6 +381,12 @@ impl TrayManager {
         changed
     }
 
+    /// Whether the current icon is a marquee or spinner that needs regular redraws
+    pub fn is_animating<R: Runtime>(app: &AppHandle<R>) -> bool {
+        app.try_state::<TrayManager>()
+            .is_some_and(|manager| manager.compositor.is_animating())
+    }
+
     /// Update the tray icon with current track info.
     /// Uses the TrayManager singleton stored in app state.
     /// Also checks for dark mode changes and re-renders if needed.
@@ -265,25 +397,13 @@ impl TrayManager {
         let manager = app.try_state::<TrayManager>()
             .context("TrayManager not found in app state")?;
 
-        let state_guard = state.read();
+        let artwork = app.try_state::<Arc<ArtworkCache>>()
+            .context("ArtworkCache not found in app state")?;
 
-        let icon_bytes = match &state_guard.current_track {
-            Some(track) if track.state == PlaybackState::Playing => {
-                // Show track info with artwork when playing
-                manager.compositor.create_menu_bar_icon(
-                    track.artwork.as_deref(),
-                    &track.title,
-                    &track.artist,
-                )?
-            }
-            Some(track) if track.state == PlaybackState::Loading => {
-                // Show loading text
-                manager.compositor.create_menu_bar_icon(None, "Loading...", "")?
-            }
-            _ => {
-                // Paused, stopped, or no track - show placeholder
-                manager.compositor.create_menu_bar_icon(None, "", "")?
-            }
+        let (icon_bytes, template) = {
+            let state_guard = state.read();
+            let icon_bytes = manager.compositor.render_state(&state_guard, &artwork)?;
+            (icon_bytes, state_guard.display_settings.theme.template)
         };
 
         let image = Image::from_bytes(&icon_bytes)
@@ -291,6 +411,9 @@ impl TrayManager {
 
         if let Some(tray) = app.try_state::<tauri::tray::TrayIcon<R>>() {
             tray.set_icon(Some(image))?;
+            if manager.template.swap(template, Ordering::Relaxed) != template {
+                tray.set_icon_as_template(template)?;
+            }
         }
 
         Ok(())
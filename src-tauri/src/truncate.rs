@@ -0,0 +1,268 @@
+//! Shortening text to fit a width
+//!
+//! Text is only ever cut between grapheme clusters, so accents stay on their
+//! letters and emoji sequences (flags, skin tones, ZWJ families) stay whole.
+//! Widths come from the caller, so the result matches what is drawn.
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use std::ops::Range;
+
+pub const ELLIPSIS: &str = "...";
+
+/// Separators left dangling by a cut, dropped along with whitespace
+const DANGLING: &[char] = &['-', '\u{2013}', '\u{2014}', '\u{00B7}', '|', '/', ',', ':', ';'];
+
+/// Where text that doesn't fit is shortened
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+    /// "Very Long Ti..."
+    #[default]
+    End,
+    /// "Very Lo...itle"
+    Middle,
+    /// "Very Long..." - cut after the last whole word that fits
+    Word,
+    /// "Title - Art..." - shorten the artist credit before touching the title
+    ArtistFirst,
+}
+
+/// Shorten `text` so that `measure` of the result is at most `max_width`
+/// `artist` is the byte range of the artist credit, used by `ArtistFirst`
+/// Returns "" if not even the ellipsis fits
+pub fn truncate(
+    text: &str,
+    artist: Option<Range<usize>>,
+    strategy: Truncation,
+    max_width: f32,
+    measure: impl Fn(&str) -> f32,
+) -> String {
+    if measure(text) <= max_width {
+        return text.to_string();
+    }
+    if measure(ELLIPSIS) > max_width {
+        return String::new();
+    }
+
+    let fits = |candidate: &str| measure(candidate) <= max_width;
+    match strategy {
+        Truncation::End => end(text, &fits),
+        Truncation::Middle => middle(text, &fits),
+        Truncation::Word => word(text, &fits),
+        Truncation::ArtistFirst => artist
+            .filter(|range| !range.is_empty() && text.get(range.clone()).is_some())
+            .and_then(|range| artist_first(text, range, &fits))
+            .unwrap_or_else(|| end(text, &fits)),
+    }
+}
+
+/// Byte offsets of every grapheme boundary, from 0 to `text.len()`
+fn boundaries(text: &str) -> Vec<usize> {
+    text.grapheme_indices(true)
+        .map(|(index, _)| index)
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+/// Largest `n` in `0..=max` for which `fits(n)`, given that `fits` only
+/// turns false as `n` grows; 0 if nothing does
+fn largest_fitting(max: usize, fits: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, max);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+fn is_dangling(ch: char) -> bool {
+    ch.is_whitespace() || DANGLING.contains(&ch)
+}
+
+/// "Title -" becomes "Title..."
+fn with_ellipsis(head: &str) -> String {
+    format!("{}{}", head.trim_end_matches(is_dangling), ELLIPSIS)
+}
+
+fn end(text: &str, fits: &impl Fn(&str) -> bool) -> String {
+    let bounds = boundaries(text);
+    let kept = largest_fitting(bounds.len() - 1, |n| fits(&with_ellipsis(&text[..bounds[n]])));
+    with_ellipsis(&text[..bounds[kept]])
+}
+
+fn middle(text: &str, fits: &impl Fn(&str) -> bool) -> String {
+    let bounds = boundaries(text);
+    let count = bounds.len() - 1;
+    // The head gets the extra grapheme when the count is odd
+    let candidate = |n: usize| {
+        let head = &text[..bounds[n.div_ceil(2)]];
+        let tail = &text[bounds[count - n / 2]..];
+        format!("{}{}", with_ellipsis(head), tail.trim_start_matches(is_dangling))
+    };
+    let kept = largest_fitting(count.saturating_sub(1), |n| fits(&candidate(n)));
+    candidate(kept)
+}
+
+fn word(text: &str, fits: &impl Fn(&str) -> bool) -> String {
+    let bounds = boundaries(text);
+    let kept = largest_fitting(bounds.len() - 1, |n| fits(&with_ellipsis(&text[..bounds[n]])));
+    let limit = bounds[kept];
+
+    // Only cut where a word meets whitespace; scripts written without
+    // spaces have no such place and fall back to cutting at the end
+    let cut = text.split_word_bound_indices()
+        .rev()
+        .map(|(index, _)| index)
+        .filter(|&index| index <= limit && !text[..index].trim().is_empty())
+        .find(|&index| {
+            text[..index].ends_with(char::is_whitespace) || text[index..].starts_with(char::is_whitespace)
+        });
+
+    match cut {
+        Some(index) => with_ellipsis(&text[..index]),
+        None => with_ellipsis(&text[..limit]),
+    }
+}
+
+/// Shorten only the artist credit, or None if not even its first grapheme fits
+fn artist_first(text: &str, artist: Range<usize>, fits: &impl Fn(&str) -> bool) -> Option<String> {
+    let (before, credit, after) = (&text[..artist.start], &text[artist.clone()], &text[artist.end..]);
+    let bounds = boundaries(credit);
+    let candidate = |n: usize| format!("{}{}{}", before, with_ellipsis(&credit[..bounds[n]]), after);
+
+    let kept = largest_fitting(bounds.len() - 1, |n| n > 0 && fits(&candidate(n)));
+    (kept > 0).then(|| candidate(kept))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One unit per grapheme, like a monospaced font that draws clusters whole
+    fn graphemes(text: &str) -> f32 {
+        text.graphemes(true).count() as f32
+    }
+
+    fn cut(text: &str, strategy: Truncation, max_width: f32) -> String {
+        truncate(text, None, strategy, max_width, graphemes)
+    }
+
+    const STRATEGIES: [Truncation; 4] = [Truncation::End, Truncation::Middle, Truncation::Word, Truncation::ArtistFirst];
+
+    #[test]
+    fn clusters_are_never_split() {
+        let texts = [
+            "Family 👨\u{200d}👩\u{200d}👧\u{200d}👦 road trip 👨\u{200d}👩\u{200d}👧\u{200d}👦",
+            "Flags 🇯🇵🇫🇷🇧🇷🇨🇦 and more 🇬🇧🇺🇸",
+            "Thumbs 👍🏽👍🏿👍🏻 up 👋🏾",
+            "Cafe\u{301} de\u{301}ja\u{300} vu - Ame\u{301}lie",
+            "Ha\u{308}agen-Da\u{308}zs / n\u{303}",
+        ];
+        for text in texts {
+            let original: Vec<&str> = text.graphemes(true).collect();
+            // Artist range covers the text after the first space
+            let artist = text.find(' ').map(|space| space + 1..text.len());
+            for strategy in STRATEGIES {
+                for max_width in 0..=original.len() {
+                    let max_width = max_width as f32;
+                    let result = truncate(text, artist.clone(), strategy, max_width, graphemes);
+                    assert!(graphemes(&result) <= max_width, "{:?} at {} gave {:?}", strategy, max_width, result);
+                    for grapheme in result.graphemes(true) {
+                        assert!(
+                            grapheme == "." || original.contains(&grapheme),
+                            "{:?} at {} split a cluster: {:?} in {:?}", strategy, max_width, grapheme, result
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn text_that_fits_is_unchanged() {
+        for strategy in STRATEGIES {
+            assert_eq!(cut("Short", strategy, 5.0), "Short");
+            assert_eq!(cut("", strategy, 0.0), "");
+        }
+    }
+
+    #[test]
+    fn nothing_when_the_ellipsis_does_not_fit() {
+        for strategy in STRATEGIES {
+            assert_eq!(cut("Long title", strategy, 2.0), "");
+            assert_eq!(cut("Long title", strategy, 3.0), "...");
+        }
+    }
+
+    #[test]
+    fn end_cuts_and_trims_dangling_separators() {
+        assert_eq!(cut("Hello World", Truncation::End, 8.0), "Hello...");
+        assert_eq!(cut("Title - Artist", Truncation::End, 10.0), "Title...");
+        assert_eq!(cut("Title \u{2014} Artist", Truncation::End, 11.0), "Title...");
+        assert_eq!(cut("One, Two, Three", Truncation::End, 8.0), "One...");
+        assert_eq!(cut("Side A | Side B", Truncation::End, 11.0), "Side A...");
+    }
+
+    #[test]
+    fn middle_gives_the_head_the_extra_grapheme() {
+        assert_eq!(cut("abcdefghij", Truncation::Middle, 8.0), "abc...ij");
+        assert_eq!(cut("abcdefghij", Truncation::Middle, 9.0), "abc...hij");
+        assert_eq!(cut("abcdefghij", Truncation::Middle, 4.0), "a...");
+        // Separators on either side of the cut go too
+        assert_eq!(cut("Title - Middle - Artist", Truncation::Middle, 14.0), "Title...Artist");
+    }
+
+    #[test]
+    fn word_cuts_between_words() {
+        assert_eq!(cut("The quick brown fox", Truncation::Word, 14.0), "The quick...");
+        assert_eq!(cut("The quick brown fox", Truncation::Word, 18.0), "The quick brown...");
+        assert_eq!(cut("Song - Artist Name", Truncation::Word, 12.0), "Song...");
+        // A first word that doesn't fit is cut like End
+        assert_eq!(cut("Supercalifragilistic is long", Truncation::Word, 10.0), "Superca...");
+    }
+
+    #[test]
+    fn word_falls_back_to_end_without_spaces() {
+        let cjk = "東京特許許可局長今日急遽休暇許可拒否";
+        assert_eq!(cut(cjk, Truncation::Word, 8.0), "東京特許許...");
+        assert_eq!(cut(cjk, Truncation::Word, 8.0), cut(cjk, Truncation::End, 8.0));
+        let thai = "สวัสดีครับทุกคนที่นี่";
+        assert_eq!(cut(thai, Truncation::Word, 9.0), cut(thai, Truncation::End, 9.0));
+    }
+
+    #[test]
+    fn artist_first_shortens_only_the_artist() {
+        let text = "Song Title - Very Long Artist Name";
+        let artist = Some(13..text.len());
+        assert_eq!(truncate(text, artist.clone(), Truncation::ArtistFirst, 25.0, graphemes), "Song Title - Very Long...");
+        assert_eq!(truncate(text, artist.clone(), Truncation::ArtistFirst, 17.0, graphemes), "Song Title - V...");
+
+        // The artist can come first
+        let text = "Artist Name - Title";
+        assert_eq!(truncate(text, Some(0..11), Truncation::ArtistFirst, 15.0, graphemes), "Arti... - Title");
+
+        // When the title alone is too long, the whole text is cut at the end
+        let text = "Song Title - Very Long Artist Name";
+        assert_eq!(
+            truncate(text, artist, Truncation::ArtistFirst, 15.0, graphemes),
+            cut(text, Truncation::End, 15.0)
+        );
+    }
+
+    #[test]
+    fn artist_first_falls_back_to_end_for_bad_ranges() {
+        let text = "Björk - Jóga and other songs";
+        let end = cut(text, Truncation::End, 12.0);
+        // Empty, reversed, out of bounds and in the middle of "ö"
+        let ranges = [None, Some(5..5), Some(Range { start: 9, end: 5 }), Some(0..100), Some(0..3)];
+        for range in ranges {
+            assert_eq!(truncate(text, range.clone(), Truncation::ArtistFirst, 12.0, graphemes), end, "{:?}", range);
+        }
+    }
+}
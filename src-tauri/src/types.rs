@@ -1,17 +1,51 @@
 use serde::{Deserialize, Serialize};
 use std::time::Instant;
 
+use crate::credits::CreditStyle;
+use crate::theme::Theme;
+use crate::truncate::Truncation;
+
 /// Sidecar message types - these match the JSON output from the Node.js sidecar
+///
+/// Decoding is forward compatible: unknown fields are ignored, missing metadata
+/// fields default to empty, and unknown `type` tags decode as `Unknown`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SidecarMessage {
+    /// First message of every sidecar process - announces the protocol it speaks
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
     NowPlaying {
         zone_id: String, // NEW: Zone identifier
+        #[serde(default)]
         title: String,
+        #[serde(default)]
         artist: String,
+        #[serde(default)]
         album: String,
         state: PlaybackState,
-        artwork: Option<String>,
+        /// Key into the artwork cache - the image itself arrives as an `Artwork` message
+        #[serde(default)]
+        image_key: Option<String>,
+        /// Playback position in seconds
+        #[serde(default)]
+        seek_position: Option<f64>,
+        /// Track length in seconds
+        #[serde(default)]
+        length: Option<f64>,
+    },
+    /// Playback position jumped (seek, or drift from the local estimate)
+    SeekChanged {
+        zone_id: String,
+        seek_position: f64,
+    },
+    /// Album artwork, sent once per image_key as a base64 data URL
+    Artwork {
+        image_key: String,
+        data: String,
     },
     ZoneList {
         // NEW: List of all zones
@@ -19,11 +53,84 @@ pub enum SidecarMessage {
     },
     Status {
         state: String,
+        #[serde(default)]
         message: Option<String>,
     },
     Error {
         message: String,
     },
+    /// Sidecar accepted and executed a command
+    Ack {
+        id: u64,
+    },
+    /// Sidecar failed to execute a command
+    CommandError {
+        id: u64,
+        message: String,
+    },
+    /// Message type from a newer sidecar that this app doesn't know about
+    #[serde(other)]
+    Unknown,
+}
+
+/// Optional features a sidecar can advertise in its `Hello` message
+pub mod capability {
+    /// Accepts play/pause/next/previous/stop commands
+    pub const TRANSPORT_CONTROL: &str = "transport_control";
+    /// Accepts seek commands
+    pub const SEEK: &str = "seek";
+    /// Re-sends artwork on request when the app's cache misses
+    pub const FETCH_ARTWORK: &str = "fetch_artwork";
+}
+
+/// Transport commands sent to the sidecar over stdin, one JSON object per line
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum SidecarCommand {
+    Play { zone_id: String },
+    Pause { zone_id: String },
+    #[serde(rename = "playpause")]
+    PlayPause { zone_id: String },
+    Next { zone_id: String },
+    Previous { zone_id: String },
+    Seek { zone_id: String, seconds: u32 },
+    Stop { zone_id: String },
+    /// Ask the sidecar to (re)send an `Artwork` message for a key
+    FetchArtwork { image_key: String },
+}
+
+impl SidecarCommand {
+    /// Capability the sidecar must advertise to accept this command
+    pub fn required_capability(&self) -> &'static str {
+        match self {
+            SidecarCommand::Seek { .. } => capability::SEEK,
+            SidecarCommand::FetchArtwork { .. } => capability::FETCH_ARTWORK,
+            _ => capability::TRANSPORT_CONTROL,
+        }
+    }
+
+    /// Short name used in logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            SidecarCommand::Play { .. } => "play",
+            SidecarCommand::Pause { .. } => "pause",
+            SidecarCommand::PlayPause { .. } => "playpause",
+            SidecarCommand::Next { .. } => "next",
+            SidecarCommand::Previous { .. } => "previous",
+            SidecarCommand::Seek { .. } => "seek",
+            SidecarCommand::Stop { .. } => "stop",
+            SidecarCommand::FetchArtwork { .. } => "fetch_artwork",
+        }
+    }
+}
+
+/// Command envelope written to the sidecar - the request ID is echoed back
+/// in the matching `Ack` or `CommandError` message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SidecarRequest {
+    pub id: u64,
+    #[serde(flatten)]
+    pub command: SidecarCommand,
 }
 
 /// Zone information from sidecar
@@ -32,16 +139,25 @@ pub struct ZoneInfo {
     pub zone_id: String,
     pub display_name: String,
     pub state: PlaybackState,
+    #[serde(default)]
     pub now_playing: Option<NowPlayingInfo>,
 }
 
 /// Minimal now playing info embedded in zone list
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NowPlayingInfo {
+    #[serde(default)]
     pub title: String,
+    #[serde(default)]
     pub artist: String,
+    #[serde(default)]
     pub album: String,
-    pub artwork: Option<String>,
+    #[serde(default)]
+    pub image_key: Option<String>,
+    #[serde(default)]
+    pub seek_position: Option<f64>,
+    #[serde(default)]
+    pub length: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,7 +166,63 @@ pub struct NowPlayingData {
     pub artist: String,
     pub album: String,
     pub state: PlaybackState,
-    pub artwork: Option<String>, // base64 data URL
+    pub image_key: Option<String>, // key into the artwork cache
+    pub seek_position: Option<f64>, // seconds, as of position_updated_at
+    pub length: Option<f64>,        // seconds
+    #[serde(skip, default = "Instant::now")]
+    pub position_updated_at: Instant,
+}
+
+impl NowPlayingData {
+    /// Current playback position in seconds
+    /// Interpolated locally while playing so it advances between seek updates
+    pub fn position(&self) -> Option<f64> {
+        let base = self.seek_position?;
+        let elapsed = if self.state == PlaybackState::Playing {
+            self.position_updated_at.elapsed().as_secs_f64()
+        } else {
+            0.0
+        };
+
+        let position = base + elapsed;
+        Some(match self.length {
+            Some(length) if length > 0.0 => position.min(length),
+            _ => position,
+        })
+    }
+
+    /// Fraction of the track played (0.0 - 1.0), if the length is known
+    pub fn progress(&self) -> Option<f32> {
+        let length = self.length.filter(|l| *l > 0.0)?;
+        let position = self.position()?;
+        Some((position / length).clamp(0.0, 1.0) as f32)
+    }
+
+    /// Record a new position reported by the sidecar
+    pub fn set_position(&mut self, seek_position: f64) {
+        self.seek_position = Some(seek_position);
+        self.position_updated_at = Instant::now();
+    }
+
+    /// Change playback state, freezing the interpolated position first
+    /// so pausing doesn't snap back to the last reported position
+    pub fn set_state(&mut self, state: PlaybackState) {
+        if let Some(position) = self.position() {
+            self.set_position(position);
+        }
+        self.state = state;
+    }
+}
+
+/// Format seconds as m:ss (or h:mm:ss for long tracks)
+pub fn format_duration(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (hours, minutes, secs) = (total / 3600, (total % 3600) / 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -60,6 +232,9 @@ pub enum PlaybackState {
     Paused,
     Stopped,
     Loading, // NEW: Loading state
+    /// State from a newer sidecar - treated like stopped for display purposes
+    #[serde(other)]
+    Unknown,
 }
 
 /// Zone data tracked in Rust
@@ -73,7 +248,7 @@ pub struct Zone {
 }
 
 /// Zone preference - which zone to display
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "mode", rename_all = "snake_case")]
 pub enum ZonePreference {
     Auto,
@@ -100,6 +275,126 @@ impl Default for ZonePreference {
     }
 }
 
+/// Where to draw the track progress bar in the menu bar icon
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressBarStyle {
+    #[default]
+    Off,
+    UnderArtwork,
+    UnderText,
+}
+
+/// What the menu bar icon shows
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum IconLayout {
+    #[default]
+    ArtworkAndText,
+    TextOnly,
+    ArtworkOnly,
+}
+
+/// What happens to text wider than the icon's maximum width
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TextOverflow {
+    /// Cut off with an ellipsis
+    #[default]
+    Truncate,
+    /// Scroll the full text while playing
+    Marquee,
+}
+
+/// What the icon shows while the track is paused
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PausedStyle {
+    /// The track's artwork and text, dimmed, with a pause symbol
+    #[default]
+    Dimmed,
+    /// The macaroon placeholder, as when nothing is playing
+    Placeholder,
+}
+
+/// How a colour taken from the album art is used
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtworkColor {
+    /// Theme colours only
+    #[default]
+    Off,
+    /// Track text in the artwork's colour
+    Text,
+    /// A line in the artwork's colour under the text
+    Underline,
+    /// A rounded background in the artwork's colour behind the text
+    Pill,
+}
+
+/// How the menu bar icon is rendered
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub progress_bar: ProgressBarStyle,
+    /// Track text size in points
+    pub font_size: f32,
+    /// Widest the icon may grow, in points
+    pub max_width: u32,
+    pub layout: IconLayout,
+    pub overflow: TextOverflow,
+    pub paused: PausedStyle,
+    /// Where truncated text is shortened
+    pub truncation: Truncation,
+    /// Menu bar text, see `template` for the syntax
+    pub template: String,
+    /// How `{credits}` shows multiple artists
+    pub credits: CreditStyle,
+    /// Font files or family names tried before the system fonts
+    pub fonts: Vec<String>,
+    /// Icon colours for light and dark menu bars
+    pub theme: Theme,
+    /// Colour the text, an underline or a pill from the artwork; it also
+    /// replaces the theme's accent
+    pub artwork_color: ArtworkColor,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            progress_bar: ProgressBarStyle::Off,
+            font_size: 21.0,
+            max_width: 500,
+            layout: IconLayout::ArtworkAndText,
+            overflow: TextOverflow::Truncate,
+            paused: PausedStyle::Dimmed,
+            truncation: Truncation::End,
+            template: crate::template::DEFAULT_TEMPLATE.to_string(),
+            credits: CreditStyle::First,
+            fonts: Vec::new(),
+            theme: Theme::default(),
+            artwork_color: ArtworkColor::Off,
+        }
+    }
+}
+
+/// Zone-following behaviour applied when a zone is selected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BehaviorSettings {
+    pub smart_switching: bool,
+    pub grace_period_mins: u32,
+}
+
+impl Default for BehaviorSettings {
+    fn default() -> Self {
+        Self {
+            smart_switching: default_smart_switching(),
+            grace_period_mins: default_grace_period(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     // Existing fields
@@ -116,6 +411,16 @@ pub struct AppState {
 
     // Track last known dark mode for detecting changes
     pub last_dark_mode: Option<bool>,
+
+    // Sidecar messages dropped because they were unknown or malformed
+    pub skipped_messages: u64,
+
+    // Menu bar rendering options
+    pub display_settings: DisplaySettings,
+    pub behavior_settings: BehaviorSettings,
+
+    // Invalid values found in the settings file, shown in the menu
+    pub settings_problems: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -123,6 +428,8 @@ pub enum ConnectionStatus {
     Disconnected,
     Discovering,
     Connected,
+    /// Sidecar speaks a protocol this app doesn't understand
+    Incompatible(String),
     Error(String),
 }
 
@@ -138,6 +445,10 @@ impl Default for AppState {
             is_smart_switched: false,
             last_menu_rebuild: None,
             last_dark_mode: None,
+            skipped_messages: 0,
+            display_settings: DisplaySettings::default(),
+            behavior_settings: BehaviorSettings::default(),
+            settings_problems: Vec::new(),
         }
     }
 }